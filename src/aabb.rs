@@ -0,0 +1,50 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::Point3;
+use crate::stats::{self, Counter};
+
+/// Axis aligned bounding box.
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    /// Builds the box spanned by two opposite corners, in any order.
+    pub fn new(a: &Point3, b: &Point3) -> Self {
+        Self {
+            min: Point3::build(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point3::build(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn size(&self) -> Point3 {
+        self.max - self.min
+    }
+
+    /// Slab test, returns the part of `ray_t` where the ray is inside the box.
+    pub fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Interval> {
+        stats::count(Counter::BoxTests);
+        let origin = r.origin();
+        let direction = r.direction();
+        let mut t_min = ray_t.min;
+        let mut t_max = ray_t.max;
+
+        for axis in 0..3 {
+            let inv_d = 1.0 / direction[axis];
+            let mut t0 = (self.min[axis] - origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN from 0 * inf (ray in the slab plane) is ignored by max/min
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+
+        Some(Interval::with_bounds(t_min, t_max))
+    }
+}
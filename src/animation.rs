@@ -0,0 +1,546 @@
+use std::cell::Cell;
+use std::io;
+use std::ops::{Add, Mul, Sub};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::camera::Camera;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::progress::RenderSummary;
+use crate::projection::Projection;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::{Point3, Vec3};
+use raytracing::degrees_to_radians;
+use raytracing::rng::{hash_seed, Rng};
+
+/// Values that can be keyframed: anything that can be blended linearly.
+pub trait Animatable: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self> {}
+
+impl<T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>> Animatable for T {}
+
+/// How a `Track` gets from one keyframe to the next.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    Linear,
+    /// Smooth curve through all keyframes, with the velocity at each key given by its
+    /// neighbours.
+    CatmullRom,
+    /// Cubic Bézier curve from each key through its `out_handle` and the next key's
+    /// `in_handle` to the next key.
+    Bezier,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe<T> {
+    /// Time in seconds.
+    pub time: f32,
+    pub value: T,
+    /// Bézier control points before and after the key. They default to the value itself,
+    /// which eases in and out of the key.
+    pub in_handle: T,
+    pub out_handle: T,
+}
+
+impl<T: Animatable> Keyframe<T> {
+    pub fn new(time: f32, value: T) -> Self {
+        Self { time, value, in_handle: value, out_handle: value }
+    }
+}
+
+/// A value changing over time, given by keyframes. Before the first and after the last key
+/// the value stays at that key's.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self { keys: Vec::new(), interpolation }
+    }
+
+    /// Adds `key`, keeping the keys ordered by time. A key at the time of an existing key
+    /// replaces it.
+    pub fn add(&mut self, key: Keyframe<T>) {
+        let index = self.keys.partition_point(|k| k.time < key.time);
+        match self.keys.get_mut(index) {
+            Some(existing) if existing.time == key.time => *existing = key,
+            _ => self.keys.insert(index, key),
+        }
+    }
+
+    /// The track with a key of `value` at `time` added.
+    pub fn with_key(mut self, time: f32, value: T) -> Self {
+        self.add(Keyframe::new(time, value));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn keys(&self) -> &[Keyframe<T>] {
+        &self.keys
+    }
+
+    /// Value at `time`. Panics if the track has no keys.
+    pub fn evaluate(&self, time: f32) -> T {
+        let keys = &self.keys;
+        let (first, last) = (keys.first().expect("track has no keys"), keys.last().unwrap());
+        if time <= first.time {
+            return first.value;
+        }
+        if time >= last.time {
+            return last.value;
+        }
+
+        let i = keys.partition_point(|k| k.time <= time) - 1;
+        let (a, b) = (&keys[i], &keys[i + 1]);
+        let dt = b.time - a.time;
+        let s = (time - a.time) / dt;
+        match self.interpolation {
+            Interpolation::Linear => a.value + (b.value - a.value) * s,
+            Interpolation::CatmullRom => {
+                // Velocity at key j, one sided at the ends of the track
+                let velocity = |j: usize| {
+                    let (prev, next) = (&keys[j.saturating_sub(1)], &keys[(j + 1).min(keys.len() - 1)]);
+                    (next.value - prev.value) * (1.0 / (next.time - prev.time))
+                };
+                // Cubic Hermite basis
+                let (s2, s3) = (s * s, s * s * s);
+                a.value * (2.0 * s3 - 3.0 * s2 + 1.0)
+                    + velocity(i) * ((s3 - 2.0 * s2 + s) * dt)
+                    + b.value * (3.0 * s2 - 2.0 * s3)
+                    + velocity(i + 1) * ((s3 - s2) * dt)
+            }
+            Interpolation::Bezier => {
+                let u = 1.0 - s;
+                a.value * (u * u * u) + a.out_handle * (3.0 * u * u * s) + b.in_handle * (3.0 * u * s * s) + b.value * (s * s * s)
+            }
+        }
+    }
+}
+
+/// Current time of an animation in seconds, shared between whoever advances it and the
+/// `Animated` objects following it. Also keeps the time of the previous frame, which motion
+/// is measured from.
+#[derive(Clone, Default, Debug)]
+pub struct Clock(Rc<Cell<(f32, f32)>>);
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn time(&self) -> f32 {
+        self.0.get().1
+    }
+
+    pub fn previous_time(&self) -> f32 {
+        self.0.get().0
+    }
+
+    /// Sets the time without any motion since the previous frame, as for a still image.
+    #[cfg(test)]
+    pub fn set(&self, time: f32) {
+        self.0.set((time, time));
+    }
+
+    pub fn set_frame(&self, previous_time: f32, time: f32) {
+        self.0.set((previous_time, time));
+    }
+}
+
+/// Keyframed camera parameters. Parameters without a track keep the camera's setting.
+#[derive(Clone, Default, Debug)]
+pub struct CameraAnimation {
+    pub position: Option<Track<Point3>>,
+    /// Point looked at. Without it the camera keeps looking in the same direction.
+    pub target: Option<Track<Point3>>,
+    /// Field of view in degrees, vertical for the perspective projection.
+    pub fov: Option<Track<f32>>,
+    /// Focus distance of the depth of field or the realistic lens.
+    pub focus_distance: Option<Track<f32>>,
+}
+
+impl CameraAnimation {
+    /// Sets up `camera` as it is at `time`.
+    pub fn apply(&self, camera: &mut Camera, time: f32) {
+        let position = self.position.as_ref().map_or(camera.lookfrom(), |track| track.evaluate(time));
+        let target = self.target.as_ref().map_or(position + camera.view_direction(), |track| track.evaluate(time));
+        camera.look_at(position, target, camera.vup());
+
+        if let Some(track) = &self.fov {
+            match &mut camera.projection {
+                Projection::Perspective { vfov } => *vfov = track.evaluate(time),
+                Projection::FisheyeEquidistant { fov } | Projection::FisheyeEquisolid { fov } => *fov = track.evaluate(time),
+                Projection::Orthographic { .. } | Projection::Equirectangular => {}
+            }
+        }
+        if let Some(track) = &self.focus_distance {
+            let distance = track.evaluate(time);
+            if let Some(depth_of_field) = &mut camera.depth_of_field {
+                depth_of_field.focus_distance = distance;
+            }
+            // A lens that can't focus that close stays where it was
+            if let Some(lens) = &mut camera.lens {
+                lens.focus(distance);
+            }
+        }
+    }
+}
+
+/// Object moved by keyframed tracks following `clock`: rotated around the y axis through its
+/// origin, then translated.
+pub struct Animated {
+    object: Rc<dyn Hittable>,
+    clock: Clock,
+    pub translation: Option<Track<Vec3>>,
+    /// Rotation in degrees, counterclockwise seen from above.
+    pub rotation_y: Option<Track<f32>>,
+}
+
+impl Animated {
+    pub fn new(object: Rc<dyn Hittable>, clock: Clock) -> Self {
+        Self { object, clock, translation: None, rotation_y: None }
+    }
+
+    /// Translation, and sine and cosine of the rotation at the current time.
+    fn transform(&self) -> (Vec3, f32, f32) {
+        self.transform_at(self.clock.time())
+    }
+
+    fn transform_at(&self, time: f32) -> (Vec3, f32, f32) {
+        let translation = self.translation.as_ref().map_or(Vec3::new(), |track| track.evaluate(time));
+        let angle = degrees_to_radians(self.rotation_y.as_ref().map_or(0.0, |track| track.evaluate(time)));
+        (translation, angle.sin(), angle.cos())
+    }
+}
+
+fn rotate_y(v: &Vec3, sin: f32, cos: f32) -> Vec3 {
+    Vec3::build(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+}
+
+impl Hittable for Animated {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord, rng: &mut Rng) -> bool {
+        let (translation, sin, cos) = self.transform();
+        let origin = rotate_y(&(r.origin() - translation), -sin, cos);
+        let direction = rotate_y(&r.direction(), -sin, cos);
+        rec.motion = Vec3::new();
+        if !self.object.hit(&Ray::new(&origin, &direction), ray_t, rec, rng) {
+            return false;
+        }
+        // Where the point on the object was in the previous frame, including its own motion
+        // if it is animated as well
+        let (previous_translation, previous_sin, previous_cos) = self.transform_at(self.clock.previous_time());
+        let previous_point = rotate_y(&(rec.point - rec.motion), previous_sin, previous_cos) + previous_translation;
+        rec.point = rotate_y(&rec.point, sin, cos) + translation;
+        rec.normal = rotate_y(&rec.normal, sin, cos);
+        rec.motion = rec.point - previous_point;
+        true
+    }
+
+    fn transmittance(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> f32 {
+        let (translation, sin, cos) = self.transform();
+        let origin = rotate_y(&(r.origin() - translation), -sin, cos);
+        self.object.transmittance(&Ray::new(&origin, &rotate_y(&r.direction(), -sin, cos)), ray_t, rng)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let (translation, sin, cos) = self.transform();
+        self.object.pdf_value(&rotate_y(&(*origin - translation), -sin, cos), &rotate_y(direction, -sin, cos))
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (translation, sin, cos) = self.transform();
+        rotate_y(&self.object.random(&rotate_y(&(*origin - translation), -sin, cos), sampler), sin, cos)
+    }
+}
+
+/// Renders the frames `first_frame..=last_frame` of an animation, each to its own file.
+#[derive(Clone, Debug)]
+pub struct Sequence {
+    pub first_frame: u32,
+    pub last_frame: u32,
+    pub frame_rate: f32,
+    /// File name of the frames, the last run of `#` is replaced by the zero padded frame
+    /// number, e.g. `frame_####.png`. Without one the number is appended to the name.
+    pub pattern: String,
+}
+
+impl Sequence {
+    pub fn new(first_frame: u32, last_frame: u32, pattern: impl Into<String>) -> Self {
+        Self {
+            first_frame,
+            last_frame,
+            frame_rate: 24.0,
+            pattern: pattern.into(),
+        }
+    }
+
+    /// Time in seconds `frame` shows.
+    pub fn frame_time(&self, frame: u32) -> f32 {
+        frame as f32 / self.frame_rate
+    }
+
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        numbered(&self.pattern, frame)
+    }
+
+    /// Renders every frame with `camera` set up by `animation` and `clock` set to the frame's
+    /// time. Each frame gets its own seed derived from the camera's, so the noise doesn't
+    /// stick to the screen, and its own AOVs and checkpoint named after it. The motion AOV
+    /// compares each frame with the one before it. Stops at the first frame that fails to
+    /// render. The camera's seed and file names are restored afterwards.
+    pub fn render(&self, camera: &mut Camera, animation: &CameraAnimation, clock: &Clock, scene: &Scene) -> io::Result<Vec<RenderSummary>> {
+        let (seed, output_path, aov_prefix) = (camera.seed, camera.output_path.clone(), camera.aov_prefix.clone());
+        let checkpoint_path = camera.checkpoint.as_ref().map(|c| c.path.clone());
+        let checkpoint_pattern = checkpoint_path.as_ref().map(|path| path.to_string_lossy().into_owned());
+
+        let summaries = (self.first_frame..=self.last_frame)
+            .map(|frame| {
+                // Set up the previous frame first, motion is measured from there
+                let time = self.frame_time(frame);
+                let previous_time = time - 1.0 / self.frame_rate;
+                animation.apply(camera, previous_time);
+                camera.mark_previous_frame();
+                clock.set_frame(previous_time, time);
+                animation.apply(camera, time);
+
+                camera.seed = hash_seed(&[seed, frame as u64]);
+                camera.output_path = self.frame_path(frame);
+                let stem = camera.output_path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                camera.aov_prefix = format!("{}_aov", stem);
+                if let (Some(checkpointing), Some(pattern)) = (&mut camera.checkpoint, &checkpoint_pattern) {
+                    checkpointing.path = numbered(pattern, frame);
+                }
+                camera.render(scene)
+            })
+            .collect();
+        camera.seed = seed;
+        camera.output_path = output_path;
+        camera.aov_prefix = aov_prefix;
+        if let (Some(checkpointing), Some(path)) = (&mut camera.checkpoint, checkpoint_path) {
+            checkpointing.path = path;
+        }
+        summaries
+    }
+}
+
+/// `pattern` with its last run of `#` replaced by `number`, padded with zeros to the length
+/// of the run. Without one the number is appended to the file name, before the extension.
+fn numbered(pattern: &str, number: u32) -> PathBuf {
+    match pattern.rfind('#') {
+        Some(end) => {
+            let start = pattern[..end].trim_end_matches('#').len();
+            let width = end + 1 - start;
+            PathBuf::from(format!("{}{:0width$}{}", &pattern[..start], number, &pattern[end + 1..], width = width))
+        }
+        None => {
+            let path = PathBuf::from(pattern);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = path.extension().map_or(String::new(), |e| format!(".{}", e.to_string_lossy()));
+            path.with_file_name(format!("{}_{:04}{}", stem, number, extension))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+    use crate::material::{Lambertian, Material};
+    use crate::aov::Aov;
+    use crate::camera::Checkpointing;
+    use crate::sphere::Sphere;
+    use crate::color::Color;
+
+    fn track(interpolation: Interpolation) -> Track<f32> {
+        Track::new(interpolation).with_key(2.0, 4.0).with_key(0.0, 0.0).with_key(1.0, 1.0)
+    }
+
+    #[test]
+    fn test_tracks_pass_through_keys_and_hold_at_the_ends() {
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom, Interpolation::Bezier] {
+            let track = track(interpolation);
+            for key in track.keys() {
+                assert!((track.evaluate(key.time) - key.value).abs() < 1e-6, "{:?}", interpolation);
+            }
+            assert_eq!(track.evaluate(-1.0), 0.0);
+            assert_eq!(track.evaluate(5.0), 4.0);
+        }
+        assert_eq!(track(Interpolation::Linear).evaluate(1.5), 2.5);
+        assert_eq!(Track::new(Interpolation::Linear).with_key(1.0, 3.0).evaluate(0.0), 3.0);
+    }
+
+    #[test]
+    fn test_key_at_an_existing_time_replaces_it() {
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom, Interpolation::Bezier] {
+            let mut track = track(interpolation);
+            track.add(Keyframe::new(1.0, 3.0));
+            assert_eq!(track.keys().len(), 3);
+            assert_eq!(track.evaluate(1.0), 3.0);
+            // Neighbours of the replaced key still interpolate without dividing by zero
+            assert!(track.evaluate(0.5).is_finite() && track.evaluate(1.5).is_finite(), "{:?}", interpolation);
+        }
+    }
+
+    #[test]
+    fn test_catmull_rom_is_smooth_and_bezier_eases() {
+        // Catmull-Rom has the same velocity on both sides of a key
+        let track = track(Interpolation::CatmullRom);
+        let h = 1e-3;
+        let before = (track.evaluate(1.0) - track.evaluate(1.0 - h)) / h;
+        let after = (track.evaluate(1.0 + h) - track.evaluate(1.0)) / h;
+        assert!((before - after).abs() < 0.05, "{} {}", before, after);
+        assert!((after - 2.0).abs() < 0.05);
+
+        // With default handles, Bézier comes to a stop at every key
+        let track = track_vec3();
+        let near_key = (track.evaluate(0.01) - track.evaluate(0.0)).length();
+        let middle = (track.evaluate(0.51) - track.evaluate(0.5)).length();
+        assert!(near_key < middle * 0.1);
+        // Handles shape the curve without moving the keys
+        let mut handled = Track::new(Interpolation::Bezier);
+        let mut key = Keyframe::new(0.0, 0.0);
+        key.out_handle = 3.0;
+        handled.add(key);
+        handled.add(Keyframe::new(1.0, 1.0));
+        assert!(handled.evaluate(0.5) > 0.5);
+    }
+
+    fn track_vec3() -> Track<Vec3> {
+        Track::new(Interpolation::Bezier).with_key(0.0, Vec3::new()).with_key(1.0, Vec3::build(1.0, 2.0, 0.0))
+    }
+
+    #[test]
+    fn test_animated_object_follows_the_clock() {
+        let clock = Clock::new();
+        let sphere = Sphere::new(&Point3::new(), 0.5, Material::Lambertian(Lambertian::new(Color::build(0.5, 0.5, 0.5))));
+        let mut animated = Animated::new(Rc::new(sphere), clock.clone());
+        animated.translation = Some(Track::new(Interpolation::Linear).with_key(0.0, Vec3::new()).with_key(1.0, Vec3::build(2.0, 0.0, 0.0)));
+        animated.rotation_y = Some(Track::new(Interpolation::Linear).with_key(0.0, 0.0).with_key(1.0, 90.0));
+
+        let ray = Ray::new(&Point3::build(2.0, 0.0, 5.0), &Vec3::build(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(!animated.hit(&ray, &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0)));
+        clock.set(1.0);
+        assert!(animated.hit(&ray, &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0)));
+        assert!((rec.point - Point3::build(2.0, 0.0, 0.5)).length() < 1e-5);
+        assert!((rec.normal - Vec3::build(0.0, 0.0, 1.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_animated_object_reports_motion() {
+        let clock = Clock::new();
+        let sphere = Sphere::new(&Point3::new(), 0.5, Material::Lambertian(Lambertian::new(Color::build(0.5, 0.5, 0.5))));
+        let mut animated = Animated::new(Rc::new(sphere), clock.clone());
+        animated.translation = Some(Track::new(Interpolation::Linear).with_key(0.0, Vec3::new()).with_key(1.0, Vec3::build(2.0, 0.0, 0.0)));
+        animated.rotation_y = Some(Track::new(Interpolation::Linear).with_key(0.0, 0.0).with_key(1.0, 90.0));
+        let ray = Ray::new(&Point3::build(2.0, 0.0, 5.0), &Vec3::build(0.0, 0.0, -1.0));
+        let hit = |rec: &mut HitRecord| animated.hit(&ray, &Interval::with_bounds(0.001, f32::INFINITY), rec, &mut Rng::new(0));
+
+        let mut rec = HitRecord::new();
+        clock.set(1.0);
+        assert!(hit(&mut rec));
+        assert_eq!(rec.motion, Vec3::new());
+
+        // Half a second earlier the point on the sphere, at (-0.5, 0, 0) in its own space, was
+        // rotated by 45° instead of 90° and translated by 1 instead of 2
+        clock.set_frame(0.5, 1.0);
+        assert!(hit(&mut rec));
+        let previous = Point3::build(1.0 - 0.5 * FRAC_1_SQRT_2, 0.0, 0.5 * FRAC_1_SQRT_2);
+        assert!((rec.motion - (rec.point - previous)).length() < 1e-5, "{:?}", rec.motion);
+
+        // Nested animations add up
+        let mut outer = Animated::new(Rc::new(animated), clock.clone());
+        outer.translation = Some(Track::new(Interpolation::Linear).with_key(0.0, Vec3::new()).with_key(1.0, Vec3::build(0.0, 1.0, 0.0)));
+        let ray = Ray::new(&Point3::build(2.0, 1.0, 5.0), &Vec3::build(0.0, 0.0, -1.0));
+        assert!(outer.hit(&ray, &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0)));
+        let previous = previous + Vec3::build(0.0, 0.5, 0.0);
+        assert!((rec.motion - (rec.point - previous)).length() < 1e-5, "{:?}", rec.motion);
+    }
+
+    #[test]
+    fn test_sequence_motion_compares_with_the_previous_frame() {
+        let directory = std::env::temp_dir().join(format!("raytracing_test_motion_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        // Sphere in front of the camera moving a quarter unit to the right every frame
+        let clock = Clock::new();
+        let sphere = Sphere::new(&Point3::build(0.0, 0.0, -3.0), 1.0, Material::Lambertian(Lambertian::new(Color::build(0.5, 0.5, 0.5))));
+        let mut animated = Animated::new(Rc::new(sphere), clock.clone());
+        let moving = Track::new(Interpolation::Linear).with_key(0.0, Vec3::new()).with_key(1.0, Vec3::build(1.0, 0.0, 0.0));
+        animated.translation = Some(moving);
+        let mut scene = Scene::new();
+        scene.add(Rc::new(animated));
+
+        let center_motion = |animation: &CameraAnimation| {
+            let mut camera = Camera::new(1.0, 8);
+            camera.samples_per_pixel = 1;
+            camera.aovs = vec![Aov::Motion, Aov::Depth];
+            let mut sequence = Sequence::new(2, 2, directory.join("frame_#.png").to_string_lossy());
+            sequence.frame_rate = 4.0;
+            sequence.render(&mut camera, animation, &clock, &scene).unwrap();
+            let aov = |name: &str| image::open(directory.join(format!("frame_2_aov_{}.exr", name))).unwrap().to_rgb32f();
+            let [dx, dy, _] = aov("motion").get_pixel(4, 4).0;
+            (dx, dy, aov("depth").get_pixel(4, 4).0[0])
+        };
+
+        // Moving a quarter unit sideways at `depth` shifts a point by a quarter of the 4 pixel
+        // half width of the 90° view divided by the depth
+        let (dx, dy, depth) = center_motion(&CameraAnimation::default());
+        assert!((dx - 4.0 * 0.25 / depth).abs() < 1e-3 && dy.abs() < 1e-3, "{} {} {}", dx, dy, depth);
+
+        // A camera following the sphere sees it standing still
+        let following = CameraAnimation {
+            position: Some(Track::new(Interpolation::Linear).with_key(0.0, Point3::new()).with_key(1.0, Point3::build(1.0, 0.0, 0.0))),
+            ..CameraAnimation::default()
+        };
+        let (dx, dy, _) = center_motion(&following);
+        assert!(dx.abs() < 1e-3 && dy.abs() < 1e-3, "{} {}", dx, dy);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_sequence_restores_the_camera() {
+        let directory = std::env::temp_dir().join(format!("raytracing_test_sequence_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut camera = Camera::new(1.0, 4);
+        camera.samples_per_pixel = 1;
+        camera.seed = 7;
+        camera.output_path = directory.join("still.png");
+        camera.aov_prefix = String::from("still_aov");
+        camera.checkpoint = Some(Checkpointing::new(directory.join("still.checkpoint")));
+
+        let sequence = Sequence::new(0, 1, directory.join("frame_#.png").to_string_lossy());
+        sequence.render(&mut camera, &CameraAnimation::default(), &Clock::new(), &Scene::new()).unwrap();
+        assert!(directory.join("frame_1.png").exists());
+        assert_eq!(camera.seed, 7);
+        assert_eq!(camera.output_path, directory.join("still.png"));
+        assert_eq!(camera.aov_prefix, "still_aov");
+        assert_eq!(camera.checkpoint.unwrap().path, directory.join("still.checkpoint"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_camera_animation_and_frame_names() {
+        let mut camera = Camera::new(1.0, 8);
+        let animation = CameraAnimation {
+            position: Some(Track::new(Interpolation::Linear).with_key(0.0, Point3::new()).with_key(1.0, Point3::build(0.0, 0.0, 4.0))),
+            target: Some(Track::new(Interpolation::Linear).with_key(0.0, Point3::build(0.0, 0.0, -1.0))),
+            fov: Some(Track::new(Interpolation::Linear).with_key(0.0, 90.0).with_key(1.0, 30.0)),
+            focus_distance: None,
+        };
+        animation.apply(&mut camera, 0.5);
+        assert_eq!(camera.lookfrom(), Point3::build(0.0, 0.0, 2.0));
+        assert!((camera.view_direction() - Vec3::build(0.0, 0.0, -1.0)).length() < 1e-6);
+        assert_eq!(camera.projection, Projection::Perspective { vfov: 60.0 });
+
+        let sequence = Sequence::new(0, 3, "frames/shot_###.png");
+        assert_eq!(sequence.frame_path(7), PathBuf::from("frames/shot_007.png"));
+        assert_eq!(sequence.frame_time(12), 0.5);
+        assert_eq!(numbered("shot.png", 12), PathBuf::from("shot_0012.png"));
+        assert_eq!(numbered("a#b##.exr", 3), PathBuf::from("a#b03.exr"));
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use image::{ImageResult, Rgb, Rgb32FImage};
+
+use crate::color::Color;
+use crate::vec3::{Point3, Vec3};
+
+/// Arbitrary output variables: auxiliary images describing what the camera sees first,
+/// rendered alongside the beauty image for compositing and denoising.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Aov {
+    /// Distance of the first hit along the camera's viewing direction, infinite for the
+    /// background.
+    Depth,
+    /// Geometric world-space normal pointing out of the surface.
+    Normal,
+    /// World-space normal used for shading, facing the camera.
+    ShadingNormal,
+    /// Base color of the material, or the background color.
+    Albedo,
+    /// `Material::id` of the first sample's hit, 0 for the background.
+    MaterialId,
+    /// `HitRecord::object_id` of the first sample's hit, 0 for the background.
+    ObjectId,
+    /// Surface coordinates in red and green.
+    Uv,
+    /// World-space position.
+    Position,
+    /// Screen-space motion of what is seen since the previous frame in pixels, in red and
+    /// green, from the camera's previous view (`Camera::mark_previous_frame`) and the
+    /// `HitRecord::motion` of animated objects. Zero for a still image.
+    Motion,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 9] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::ShadingNormal,
+        Aov::Albedo,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::Uv,
+        Aov::Position,
+        Aov::Motion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::ShadingNormal => "shading_normal",
+            Aov::Albedo => "albedo",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Uv => "uv",
+            Aov::Position => "position",
+            Aov::Motion => "motion",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+}
+
+/// What one camera sample saw at its first hit, filled in by `Camera::ray_color`.
+#[derive(Copy, Clone, Debug)]
+pub struct AovSample {
+    pub hit: bool,
+    pub depth: f32,
+    pub normal: Vec3,
+    pub shading_normal: Vec3,
+    pub albedo: Color,
+    pub material_id: u32,
+    pub object_id: u32,
+    pub uv: (f32, f32),
+    pub position: Point3,
+    pub motion: (f32, f32),
+}
+
+impl AovSample {
+    pub fn new() -> Self {
+        Self {
+            hit: false,
+            depth: f32::INFINITY,
+            normal: Vec3::new(),
+            shading_normal: Vec3::new(),
+            albedo: Color::new(),
+            material_id: 0,
+            object_id: 0,
+            uv: (0.0, 0.0),
+            position: Point3::new(),
+            motion: (0.0, 0.0),
+        }
+    }
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Running averages of the samples of one pixel. Geometric values are averaged over the
+/// samples that hit something and the albedo over all samples, while the IDs, which can't be
+/// averaged, come from the pixel's first sample.
+#[derive(Copy, Clone, Debug)]
+pub struct AovPixel {
+    samples: u32,
+    hits: u32,
+    sum: AovSample,
+}
+
+impl AovPixel {
+    pub fn new() -> Self {
+        Self { samples: 0, hits: 0, sum: AovSample { depth: 0.0, ..AovSample::new() } }
+    }
+
+    pub fn add(&mut self, sample: &AovSample) {
+        if self.samples == 0 {
+            self.sum.material_id = sample.material_id;
+            self.sum.object_id = sample.object_id;
+        }
+        self.samples += 1;
+        self.sum.albedo += sample.albedo;
+        if sample.hit {
+            self.hits += 1;
+            self.sum.depth += sample.depth;
+            self.sum.normal += sample.normal;
+            self.sum.shading_normal += sample.shading_normal;
+            self.sum.uv = (self.sum.uv.0 + sample.uv.0, self.sum.uv.1 + sample.uv.1);
+            self.sum.position += sample.position;
+            self.sum.motion = (self.sum.motion.0 + sample.motion.0, self.sum.motion.1 + sample.motion.1);
+        }
+    }
+
+    /// The pixel's value of `aov` as the three channels written to its image.
+    pub fn value(&self, aov: Aov) -> [f32; 3] {
+        let hits = self.hits.max(1) as f32;
+        let unit = |v: Vec3| if v.length_squared() > 0.0 { Vec3::normalize(&v) } else { v };
+        let rgb = |v: Vec3| [v.x, v.y, v.z];
+        match aov {
+            Aov::Depth if self.hits == 0 => [f32::INFINITY; 3],
+            Aov::Depth => [self.sum.depth / hits; 3],
+            Aov::Normal => rgb(unit(self.sum.normal)),
+            Aov::ShadingNormal => rgb(unit(self.sum.shading_normal)),
+            Aov::Albedo => rgb(self.sum.albedo / self.samples.max(1) as f32),
+            Aov::MaterialId => [self.sum.material_id as f32; 3],
+            Aov::ObjectId => [self.sum.object_id as f32; 3],
+            Aov::Uv => [self.sum.uv.0 / hits, self.sum.uv.1 / hits, 0.0],
+            Aov::Position => rgb(self.sum.position / hits),
+            Aov::Motion => [self.sum.motion.0 / hits, self.sum.motion.1 / hits, 0.0],
+        }
+    }
+}
+
+impl Default for AovPixel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Image of `AovPixel`s, see `Framebuffer::aovs`.
+#[derive(Clone)]
+pub struct AovBuffer {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<AovPixel>,
+}
+
+impl AovBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![AovPixel::new(); (width * height) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &AovPixel {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut AovPixel {
+        &mut self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Linear floating point image of one AOV.
+    pub fn image(&self, aov: Aov) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.height, |x, y| Rgb(self.pixel(x, y).value(aov)))
+    }
+
+    /// Writes every AOV in `aovs` to `<prefix>_<name>.exr` in `directory`.
+    pub fn save<P: AsRef<Path>>(&self, aovs: &[Aov], directory: P, prefix: &str) -> ImageResult<()> {
+        for &aov in aovs {
+            self.image(aov).save(directory.as_ref().join(format!("{}_{}.exr", prefix, aov.name())))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_averages_hits_and_keeps_first_ids() {
+        let mut pixel = AovPixel::new();
+        let mut sample = AovSample::new();
+        sample.hit = true;
+        sample.depth = 2.0;
+        sample.normal = Vec3::build(0.0, 1.0, 0.0);
+        sample.albedo = Color::build(1.0, 0.0, 0.0);
+        sample.object_id = 3;
+        pixel.add(&sample);
+        sample.depth = 4.0;
+        sample.object_id = 5;
+        pixel.add(&sample);
+        // A sample seeing the black background
+        pixel.add(&AovSample::new());
+
+        assert_eq!(pixel.value(Aov::Depth), [3.0; 3]);
+        assert_eq!(pixel.value(Aov::Normal), [0.0, 1.0, 0.0]);
+        assert!((pixel.value(Aov::Albedo)[0] - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(pixel.value(Aov::ObjectId), [3.0; 3]);
+        assert_eq!(AovPixel::new().value(Aov::Depth), [f32::INFINITY; 3]);
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for aov in Aov::ALL {
+            assert_eq!(Aov::from_name(aov.name()), Some(aov));
+        }
+        assert_eq!(Aov::from_name("beauty"), None);
+    }
+}
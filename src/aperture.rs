@@ -0,0 +1,133 @@
+use std::f32::consts::PI;
+use std::path::Path;
+use std::rc::Rc;
+
+use image::{ImageError, ImageResult};
+
+use crate::distribution::Distribution2D;
+use raytracing::degrees_to_radians;
+
+/// Shape of the opening of a thin lens, which is the shape out of focus highlights take.
+#[derive(Clone)]
+pub enum Aperture {
+    Circle,
+    /// Regular polygon formed by `blades` straight aperture blades, turned by `rotation`
+    /// degrees counterclockwise from a corner pointing right.
+    Polygon { blades: u32, rotation: f32 },
+    /// Arbitrary shape given by a grayscale mask.
+    Image(Rc<ApertureImage>),
+}
+
+impl Aperture {
+    /// Maps two uniform numbers to a point in the aperture, spread over [-1, 1]^2 with y up.
+    /// Points are uniformly distributed over the shape, or proportionally to the mask's
+    /// transmission.
+    pub fn sample(&self, u: (f32, f32)) -> (f32, f32) {
+        match self {
+            Aperture::Circle => concentric_disk(u),
+            Aperture::Polygon { blades, rotation } => {
+                let blades = (*blades).max(3);
+                // Pick one of the equally large triangles between the center and two corners
+                let scaled = u.0 * blades as f32;
+                let corner = (scaled as u32).min(blades - 1);
+                let (a, b) = (polygon_corner(corner, blades, *rotation), polygon_corner(corner + 1, blades, *rotation));
+                let (r, t) = ((scaled - corner as f32).sqrt(), u.1);
+                (r * ((1.0 - t) * a.0 + t * b.0), r * ((1.0 - t) * a.1 + t * b.1))
+            }
+            Aperture::Image(image) => {
+                let ((x, y), _) = image.distribution.sample_continuous(u.0, u.1);
+                (2.0 * x - 1.0, 1.0 - 2.0 * y)
+            }
+        }
+    }
+}
+
+/// Corner `index` of a regular polygon with `blades` corners on the unit circle.
+fn polygon_corner(index: u32, blades: u32, rotation: f32) -> (f32, f32) {
+    let angle = degrees_to_radians(rotation) + 2.0 * PI * index as f32 / blades as f32;
+    (angle.cos(), angle.sin())
+}
+
+/// Shirley and Chiu's mapping from the unit square to the unit disk, which keeps stratified
+/// samples stratified.
+fn concentric_disk(u: (f32, f32)) -> (f32, f32) {
+    let (x, y) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Grayscale mask of an aperture, white where it lets light through.
+pub struct ApertureImage {
+    distribution: Distribution2D,
+}
+
+impl ApertureImage {
+    /// Loads a mask from any image format, using its luminance.
+    pub fn load<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let img = image::open(path)?.to_luma32f();
+        Self::from_pixels(img.width() as usize, img.height() as usize, img.as_raw()).ok_or_else(|| {
+            ImageError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, "aperture image is black"))
+        })
+    }
+
+    /// Mask from row major transmission values, `None` if nothing gets through.
+    pub fn from_pixels(width: usize, height: usize, pixels: &[f32]) -> Option<Self> {
+        if !pixels.iter().any(|&p| p > 0.0) {
+            return None;
+        }
+        let weights: Vec<f32> = pixels.iter().map(|p| p.max(0.0)).collect();
+        Some(Self { distribution: Distribution2D::new(&weights, width, height) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raytracing::rng::Rng;
+
+    fn samples(aperture: &Aperture) -> Vec<(f32, f32)> {
+        let mut rng = Rng::new(7);
+        (0..2000).map(|_| aperture.sample((rng.next_f32(), rng.next_f32()))).collect()
+    }
+
+    #[test]
+    fn test_polygon_samples_stay_inside() {
+        let blades = 5;
+        let aperture = Aperture::Polygon { blades, rotation: 90.0 };
+        // The inscribed circle of a pentagon has the radius cos(36°)
+        let apothem = (PI / blades as f32).cos();
+        let mut outside_inscribed = 0;
+        for (x, y) in samples(&aperture) {
+            let (r, angle) = ((x * x + y * y).sqrt(), y.atan2(x) - PI / 2.0);
+            // Distance to the edge of the sector the sample lies in
+            let sector = (angle.rem_euclid(2.0 * PI / blades as f32)) - PI / blades as f32;
+            assert!(r * sector.cos() <= apothem + 1e-5, "{:?}", (x, y));
+            if r > apothem {
+                outside_inscribed += 1;
+            }
+        }
+        // The corners are reached
+        assert!(outside_inscribed > 0);
+    }
+
+    #[test]
+    fn test_circle_and_image_follow_their_shape() {
+        for (x, y) in samples(&Aperture::Circle) {
+            assert!(x * x + y * y <= 1.0 + 1e-5);
+        }
+
+        // Light only gets through the top right quadrant of the mask
+        let mask = ApertureImage::from_pixels(2, 2, &[0.0, 1.0, 0.0, 0.0]).unwrap();
+        for (x, y) in samples(&Aperture::Image(Rc::new(mask))) {
+            assert!(x >= 0.0 && y >= 0.0, "{:?}", (x, y));
+        }
+        assert!(ApertureImage::from_pixels(1, 1, &[0.0]).is_none());
+    }
+}
@@ -1,137 +1,1541 @@
 use crate::vec3::{Point3, Vec3};
 use crate::hittable::{HitRecord, Hittable};
-use crate::hittable_list::HittableList;
-use crate::color::{Color, write_color};
+use crate::scene::Scene;
+use crate::color::Color;
+use crate::framebuffer::{Framebuffer, PixelStats};
+use crate::aov::{Aov, AovBuffer, AovPixel, AovSample};
+use crate::color::colors_to_image;
+use crate::denoise::Denoiser;
+use crate::filter::PixelFilter;
+use crate::projection::Projection;
+use crate::lens::RealisticLens;
+use crate::aperture::Aperture;
+use crate::checkpoint::Checkpoint;
 use crate::ray::Ray;
 use crate::interval::Interval;
-use raytracing::{random_float};
+use crate::material::ScatterRecord;
+use crate::pdf::MisHeuristic;
+use crate::sampler::{Sampler, SamplerType};
+use crate::tile::{tiles, TileOrder};
+use crate::progress::{ConsoleProgress, RenderObserver, RenderSummary};
+use crate::stats::{self, Counter, RenderStats};
+use raytracing::rng::{hash_seed, Rng};
 
-use image::{ImageBuffer};
-use indicatif::{ProgressBar, ProgressStyle};
+use std::f32::consts::PI;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const OUTPUT_PATH: &str = "output_image.png";
+
+/// Settings for spending samples only where a pixel hasn't converged yet, see `Camera::adaptive`.
+#[derive(Copy, Clone, Debug)]
+pub struct AdaptiveSampling {
+    /// Samples every pixel gets before its error is first estimated.
+    pub min_samples: u32,
+    pub max_samples: u32,
+    /// Sampling of a pixel stops once the relative standard error of its mean, and of its
+    /// neighbours' means, drops below this.
+    pub error_threshold: f32,
+    /// Samples added to every unconverged pixel per pass.
+    pub batch_size: u32,
+    /// Also write `<output>_heatmap.png` next to the output image, showing how many samples
+    /// each pixel received.
+    pub heatmap: bool,
+}
+
+impl AdaptiveSampling {
+    pub fn new(error_threshold: f32) -> Self {
+        Self {
+            min_samples: 16,
+            max_samples: 1024,
+            error_threshold,
+            batch_size: 16,
+            heatmap: false,
+        }
+    }
+}
+
+/// Settings for rendering in passes over the whole image, see `Camera::progressive`. Rendering
+/// stops at the first of the configured limits; without any it stops at `samples_per_pixel`.
+#[derive(Copy, Clone, Debug)]
+pub struct Progressive {
+    /// Samples added to every pixel per pass.
+    pub samples_per_pass: u32,
+    /// Stop once this much wall-clock time has passed. The first pass is always finished.
+    pub time_budget: Option<Duration>,
+    /// Stop once every pixel has this many samples.
+    pub target_samples: Option<u32>,
+    /// Stop once the average relative error of the pixels drops below this.
+    pub noise_threshold: Option<f32>,
+    /// How often the image so far is written to the output file.
+    pub write_interval: Option<Duration>,
+}
+
+impl Progressive {
+    pub fn new(samples_per_pass: u32) -> Self {
+        Self {
+            samples_per_pass,
+            time_budget: None,
+            target_samples: None,
+            noise_threshold: None,
+            write_interval: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// Where and how often a progressive render saves its state, see `Camera::checkpoint`.
+#[derive(Clone, Debug)]
+pub struct Checkpointing {
+    pub path: PathBuf,
+    pub interval: Duration,
+    /// Continue from the checkpoint at `path` if there is one. It has to have been made with the
+    /// same scene and camera settings.
+    pub resume: bool,
+}
+
+impl Checkpointing {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: Duration::from_secs(60),
+            resume: false,
+        }
+    }
+}
+
+/// Settings for a thin lens, see `Camera::depth_of_field`.
+#[derive(Clone)]
+pub struct DepthOfField {
+    /// Radius of the lens opening in world units, larger blurs more.
+    pub aperture_radius: f32,
+    /// Distance from the camera of the plane in focus.
+    pub focus_distance: f32,
+    pub aperture: Aperture,
+    /// How far the lens barrel cuts into the aperture toward the edges of the frame, turning
+    /// out of focus highlights into cat's eyes and darkening the corners. 0 leaves the
+    /// aperture whole, at 1 the barrel is shifted by the aperture radius in the corners.
+    pub cat_eye: f32,
+}
+
+impl DepthOfField {
+    pub fn new(aperture_radius: f32, focus_distance: f32) -> Self {
+        Self {
+            aperture_radius,
+            focus_distance,
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
+        }
+    }
+
+    /// Moves the start of the camera space ray `offset`, `direction` to a point on the lens
+    /// picked by `lens` and aims it at the point the pinhole ray would hit on the focus plane.
+    /// `film` is the film position relative to the corners, in [-1, 1] in the corners. `None`
+    /// if the lens barrel blocks the point.
+    fn ray(&self, offset: Vec3, direction: Vec3, film: (f32, f32), lens: (f32, f32)) -> Option<(Vec3, Vec3)> {
+        let (x, y) = self.aperture.sample(lens);
+        if self.cat_eye > 0.0 {
+            let (dx, dy) = (x - self.cat_eye * film.0, y - self.cat_eye * film.1);
+            if dx * dx + dy * dy > 1.0 {
+                return None;
+            }
+        }
+        let focus = offset + direction * (self.focus_distance / -direction.z);
+        let start = offset + Vec3::build(x, y, 0.0) * self.aperture_radius;
+        Some((start, focus - start))
+    }
+}
+
+/// How the views of the two eyes share the image in a stereo render.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StereoLayout {
+    /// Left eye in the left half of the image.
+    SideBySide,
+    /// Left eye in the top half of the image.
+    OverUnder,
+}
+
+/// Settings for rendering a view for each eye into one image, see `Camera::stereo`.
+#[derive(Copy, Clone, Debug)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    /// Distance between the eyes in world units.
+    pub interocular: f32,
+    /// Distance at which the eyes' views meet, things there appear at the depth of the screen.
+    /// Infinite keeps the eyes looking parallel, as headsets expect.
+    pub convergence: f32,
+}
+
+impl Stereo {
+    pub fn new(layout: StereoLayout) -> Self {
+        Self {
+            layout,
+            interocular: 0.065,
+            convergence: f32::INFINITY,
+        }
+    }
+
+    /// Moves the camera space ray `offset`, `direction` of the centered camera to the eye on
+    /// `side`, -1 for left and 1 for right. Planar projections shift the eye sideways and aim
+    /// at the same point on the convergence plane. Panoramas use omni-directional stereo
+    /// instead: the eyes sit on a circle, at right angles to the horizontal part of every ray,
+    /// so the separation is the same whichever way the viewer turns, fading out toward the poles.
+    fn eye_ray(&self, side: f32, planar: bool, offset: Vec3, direction: Vec3) -> (Vec3, Vec3) {
+        let half = side * self.interocular / 2.0;
+        let (eye, target) = if planar {
+            let eye = offset + Vec3::build(half, 0.0, 0.0);
+            (eye, offset + direction * (self.convergence / -direction.z))
+        } else {
+            let d = Vec3::normalize(&direction);
+            (offset + Vec3::build(-d.z, 0.0, d.x) * half, offset + d * self.convergence)
+        };
+        if self.convergence.is_infinite() {
+            return (eye, direction);
+        }
+        (eye, target - eye)
+    }
+}
+
+/// Placement and projection of the camera, which `Aov::Motion` compares between frames.
+#[derive(Copy, Clone, Debug)]
+struct View {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    projection: Projection,
+}
+
+impl View {
+    /// Film position in pixels of a `width` by `height` image `point` is seen at through the
+    /// pinhole of the projection, ignoring lenses and stereo.
+    fn project(&self, point: &Point3, width: u32, height: u32) -> Option<(f32, f32)> {
+        let p = *point - self.origin;
+        let camera_space = Vec3::build(Vec3::dot(&p, &self.u), Vec3::dot(&p, &self.v), Vec3::dot(&p, &self.w));
+        self.projection.project(&camera_space, width, height)
+    }
+}
 
 pub struct Camera {
     pub aspect_ratio: f32,
     pub image_width: u32,
+    /// Samples taken for every pixel, unless `adaptive` is set.
     pub samples_per_pixel: u32,
+    /// Hard limit on the number of bounces. Paths are normally ended earlier by Russian roulette.
     pub max_depth: u32,
+    /// Number of bounces before Russian roulette may terminate a path.
+    pub rr_min_bounces: u32,
+    /// How light samples and BSDF samples are weighted against each other.
+    pub mis_heuristic: MisHeuristic,
+    /// Sampler providing the random numbers for every camera, light and BSDF sample.
+    pub sampler: SamplerType,
+    /// Renders with the same seed and settings produce identical images.
+    pub seed: u64,
+    /// Varies the number of samples per pixel with the pixel's estimated error.
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Refines the whole image in passes instead of finishing one pixel after the other.
+    pub progressive: Option<Progressive>,
+    /// Periodically saves the render state so it can be resumed after an interruption.
+    pub checkpoint: Option<Checkpointing>,
+    /// Edge length of the square tiles the image is rendered in.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Where the image is written, `output_image.png` by default.
+    pub output_path: PathBuf,
+    /// Auxiliary images to write next to the beauty image, as `<aov_prefix>_<name>.exr`.
+    pub aovs: Vec<Aov>,
+    pub aov_prefix: String,
+    /// Filters the finished image, guided by the albedo and normal AOVs which are rendered
+    /// for it even if `aovs` doesn't ask for them. The unfiltered image is kept next to the
+    /// output with `_noisy` appended to its name.
+    pub denoiser: Option<Denoiser>,
+    /// How samples are weighted into the pixels around them to reconstruct the image.
+    pub filter: PixelFilter,
+    pub projection: Projection,
+    /// Traces camera rays through a real lens instead of using `projection`.
+    pub lens: Option<RealisticLens>,
+    /// Blurs what is out of focus with a thin lens. Only applies to perspective and
+    /// orthographic projections, a realistic `lens` gets its blur from its own aperture stop.
+    pub depth_of_field: Option<DepthOfField>,
+    /// Renders a view for each eye into one image, which is twice as wide or high as
+    /// `image_width` and the aspect ratio ask for. Both views sample every pixel the same way.
+    pub stereo: Option<Stereo>,
 
     image_height: u32,
-    pixel_samples_scale: f32,
     origin: Point3,
-    pixel_00_loc: Point3,
-    pixel_delta_u: Vec3,
-    pixel_delta_v: Vec3,
+    vup: Vec3,
+    /// Camera basis: right, up, and opposite to the viewing direction.
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    /// View of the previous frame of an animation, see `mark_previous_frame`.
+    previous_view: Option<View>,
 }
 
 impl Camera {
     pub fn new(aspect_ratio: f32, image_width: u32) -> Self {
         // TODO: Create a config struct for camera parameters
         let image_height = (image_width as f32 / aspect_ratio) as u32;
-        let origin = Point3::new();
         let samples_per_pixel = 100;
-        let focal_length = 1.0;
-        let viewport_height = 2.0;
         let max_depth = 50;
 
-        let pixel_samples_scale = 1.0 / samples_per_pixel as f32;
-        let viewport_width = viewport_height * aspect_ratio;
-
-        let viewport_u = Vec3::build(viewport_width, 0.0, 0.0);
-        let viewport_v = Vec3::build(0.0, -viewport_height, 0.0);
-
-        let pixel_delta_u = viewport_u / image_width as f32;
-        let pixel_delta_v = viewport_v / image_height as f32;
-
-        let viewport_upper_left = origin 
-            - Vec3::build(0.0, 0.0, focal_length) 
-            - viewport_u / 2.0 
-            - viewport_v / 2.0; 
-
-        let pixel_00_loc = viewport_upper_left + (pixel_delta_u + pixel_delta_v) * 0.5;
-
         Self {
             aspect_ratio,
             image_width,
             samples_per_pixel,
             max_depth,
+            rr_min_bounces: 3,
+            mis_heuristic: MisHeuristic::Power,
+            sampler: SamplerType::Sobol,
+            seed: 0,
+            adaptive: None,
+            progressive: None,
+            checkpoint: None,
+            tile_size: 16,
+            tile_order: TileOrder::Spiral,
+            output_path: PathBuf::from(OUTPUT_PATH),
+            aovs: Vec::new(),
+            aov_prefix: String::from("aov"),
+            denoiser: None,
+            filter: PixelFilter::default(),
+            projection: Projection::default(),
+            lens: None,
+            depth_of_field: None,
+            stereo: None,
             image_height,
-            pixel_samples_scale, 
-            origin,
-            pixel_00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
+            origin: Point3::new(),
+            vup: Vec3::build(0.0, 1.0, 0.0),
+            u: Vec3::build(1.0, 0.0, 0.0),
+            v: Vec3::build(0.0, 1.0, 0.0),
+            w: Vec3::build(0.0, 0.0, 1.0),
+            previous_view: None,
+        }
+    }
+
+    /// Places the camera at `lookfrom` looking at `lookat`, rolled so that `vup` points up
+    /// in the image. By default the camera sits in the origin looking down -z.
+    pub fn look_at(&mut self, lookfrom: Point3, lookat: Point3, vup: Vec3) {
+        self.origin = lookfrom;
+        self.vup = vup;
+        self.w = Vec3::normalize(&(lookfrom - lookat));
+        self.u = Vec3::normalize(&Vec3::cross(&vup, &self.w));
+        self.v = Vec3::cross(&self.w, &self.u);
+    }
+
+
+    /// Remembers the current placement and projection as those of the previous frame, which
+    /// `Aov::Motion` is measured from. Call it before moving the camera on to the next frame.
+    pub fn mark_previous_frame(&mut self) {
+        self.previous_view = Some(self.view());
+    }
+
+    fn view(&self) -> View {
+        View { origin: self.origin, u: self.u, v: self.v, w: self.w, projection: self.projection }
+    }
+
+    pub fn lookfrom(&self) -> Point3 {
+        self.origin
+    }
+
+    /// Unit vector the camera looks along.
+    pub fn view_direction(&self) -> Vec3 {
+        -self.w
+    }
+
+    pub fn vup(&self) -> Vec3 {
+        self.vup
+    }
+
+    /// Size of the rendered image, holding the views of both eyes when rendering stereo.
+    pub fn film_size(&self) -> (u32, u32) {
+        match self.stereo.map(|stereo| stereo.layout) {
+            None => (self.image_width, self.image_height),
+            Some(StereoLayout::SideBySide) => (2 * self.image_width, self.image_height),
+            Some(StereoLayout::OverUnder) => (self.image_width, 2 * self.image_height),
         }
     }
 
+    /// The eye seeing film position `x`, `y`, -1 for left, 1 for right and 0 without stereo,
+    /// and the position in that eye's view.
+    fn eye_at(&self, x: f32, y: f32) -> (f32, f32, f32) {
+        let (width, height) = (self.image_width as f32, self.image_height as f32);
+        match self.stereo.map(|stereo| stereo.layout) {
+            None => (0.0, x, y),
+            Some(StereoLayout::SideBySide) if x >= width => (1.0, x - width, y),
+            Some(StereoLayout::OverUnder) if y >= height => (1.0, x, y - height),
+            Some(_) => (-1.0, x, y),
+        }
+    }
 
-    pub fn render(&self, world: &HittableList) {
-        let total: u64 = u64::from( self.image_width * self.image_height );
-        let pb = ProgressBar::new(total);
+    /// Renders `scene` to `output_path` with a progress bar on the terminal.
+    pub fn render(&self, scene: &Scene) -> io::Result<RenderSummary> {
+        let summary = self.render_with(scene, &mut ConsoleProgress::new(), &AtomicBool::new(false))?;
+        Ok(summary.expect("render can't be cancelled"))
+    }
 
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"));
+    /// Renders `scene`, reporting progress and every finished tile to `observer`. Setting
+    /// `cancel` stops the render after the current tile and returns `None`; a checkpointed
+    /// render saves its state first so it can be resumed, but no image is written. Fails
+    /// before rendering anything if the checkpoint to resume from can't be used.
+    pub fn render_with(&self, scene: &Scene, observer: &mut dyn RenderObserver, cancel: &AtomicBool) -> io::Result<Option<RenderSummary>> {
+        let start = Instant::now();
+        let (width, height) = self.film_size();
 
-        let mut img = ImageBuffer::new(self.image_width, self.image_height);
+        let mut framebuffer = Framebuffer::new(width, height);
+        let progressive = self.effective_progressive();
+        let max_samples = self.max_samples(progressive.as_ref());
+        let mut sampler = self.sampler.create(max_samples, self.seed);
 
-        for y in 0..self.image_height {
-            for x in 0..self.image_width {
-                let mut pixel_color = Color::new();
-                // For each pixel, we will sample multiple rays 
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(x, y);
-                    pixel_color += self.ray_color(&ray, &world, self.max_depth);
+        let mut resumed = None;
+        let fingerprint = progressive.map(|progressive| self.fingerprint(scene, Some(&progressive)));
+        if let Some(checkpoint) = fingerprint.map(|fingerprint| self.load_checkpoint(fingerprint)).transpose()?.flatten() {
+            resumed = Some(checkpoint.pass);
+            framebuffer = checkpoint.framebuffer;
+        }
+        observer.started(width, height);
+        // Resumed renders only average the AOVs of the samples taken after resuming
+        if !self.aovs.is_empty() || self.denoiser.is_some() {
+            framebuffer.aovs = Some(AovBuffer::new(width, height));
+        }
+        // The fingerprint's probe rays aren't part of the render
+        stats::take();
+        let setup_time = start.elapsed();
+
+        let mut checkpoint_time = Duration::ZERO;
+        let passes = match (progressive, self.adaptive) {
+            (Some(progressive), _) => {
+                let fingerprint = fingerprint.unwrap_or_default();
+                let mut pass = resumed.unwrap_or(0);
+
+                let save_checkpoint = |framebuffer: &Framebuffer, pass: u32| match &self.checkpoint {
+                    Some(checkpointing) => {
+                        let checkpoint = Checkpoint { fingerprint, seed: self.seed, pass, framebuffer: framebuffer.clone() };
+                        checkpoint.save(&checkpointing.path)
+                    }
+                    None => Ok(()),
+                };
+
+                let mut last_write = Instant::now();
+                let mut last_checkpoint = Instant::now();
+                pass = self.render_progressive(scene, sampler.as_mut(), &mut framebuffer, &progressive, pass, observer, cancel, |framebuffer, pass| {
+                    if progressive.write_interval.is_some_and(|interval| last_write.elapsed() >= interval) {
+                        framebuffer.to_image().save(&self.output_path).map_err(io::Error::other)?;
+                        last_write = Instant::now();
+                    }
+                    if self.checkpoint.as_ref().is_some_and(|c| last_checkpoint.elapsed() >= c.interval) {
+                        save_checkpoint(framebuffer, pass)?;
+                        last_checkpoint = Instant::now();
+                    }
+                    Ok(())
+                })?;
+                // A render stopped by its time budget or cancelled can be continued later
+                let output_start = Instant::now();
+                save_checkpoint(&framebuffer, pass)?;
+                checkpoint_time = output_start.elapsed();
+                pass
+            }
+            (None, Some(adaptive)) => self.sample_adaptively(scene, sampler.as_mut(), &mut framebuffer, &adaptive, observer, cancel),
+            (None, None) => {
+                let counts = vec![self.samples_per_pixel; (width * height) as usize];
+                let finished = self.sample_tiles(scene, sampler.as_mut(), &mut framebuffer, &counts, || cancel.load(Ordering::Relaxed), observer);
+                if finished {
+                    observer.pass_finished(1, &framebuffer);
                 }
+                1
+            }
+        };
+        let counts = stats::take();
+        let sampling_time = start.elapsed() - setup_time - checkpoint_time;
+        if cancel.load(Ordering::Relaxed) {
+            observer.cancelled(&framebuffer);
+            return Ok(None);
+        }
 
-                write_color(x, y, &mut img, &( pixel_color * self.pixel_samples_scale ));
-                pb.inc(1);
+        let output_start = Instant::now();
+        match (&self.denoiser, &framebuffer.aovs) {
+            (Some(denoiser), Some(aovs)) => {
+                framebuffer.to_image().save(self.noisy_output_path()).map_err(io::Error::other)?;
+                let denoised = denoiser.denoise(&framebuffer, aovs);
+                colors_to_image(width, height, &denoised).save(&self.output_path).map_err(io::Error::other)?;
             }
+            _ => framebuffer.to_image().save(&self.output_path).map_err(io::Error::other)?,
+        }
+        if self.adaptive.is_some_and(|adaptive| adaptive.heatmap) {
+            framebuffer.sample_heatmap().save(self.heatmap_path()).map_err(io::Error::other)?;
+        }
+        if let Some(aovs) = &framebuffer.aovs {
+            let directory = self.output_path.parent().unwrap_or(Path::new(""));
+            aovs.save(&self.aovs, directory, &self.aov_prefix).map_err(io::Error::other)?;
         }
-        pb.finish_with_message("Done!");
-        img.save("output_image.png").expect("Failed to save image");
-    } 
+        let summary = RenderSummary {
+            width,
+            height,
+            passes,
+            total_samples: framebuffer.total_samples(),
+            elapsed: start.elapsed(),
+            stats: RenderStats {
+                counts,
+                setup_time,
+                sampling_time,
+                output_time: checkpoint_time + output_start.elapsed(),
+            },
+        };
+        observer.completed(&framebuffer, &summary);
+        Ok(Some(summary))
+    }
 
+    /// The checkpoint to continue from, if resuming is enabled and one was saved. Fails if it
+    /// can't be read or was made with a scene or settings with a different `fingerprint`.
+    fn load_checkpoint(&self, fingerprint: u64) -> io::Result<Option<Checkpoint>> {
+        let checkpointing = match self.checkpoint.as_ref().filter(|c| c.resume && c.path.exists()) {
+            Some(checkpointing) => checkpointing,
+            None => return Ok(None),
+        };
+        let path = checkpointing.path.display();
+        let checkpoint = Checkpoint::load(&checkpointing.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to load checkpoint {}: {}", path, e)))?;
+        if checkpoint.fingerprint != fingerprint {
+            let message = format!("Checkpoint {} was made with a different scene or different camera settings", path);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(Some(checkpoint))
+    }
 
-    fn ray_color(&self, ray: &Ray, world: &HittableList, depth: u32) -> Color {
-        // If the recursion depth is exceeded, return black
-        if depth <= 0 {
-            return Color::new();
+    /// Progressive settings to render with. Checkpoints are written between passes, so a
+    /// checkpointed render is progressive even if `progressive` isn't set, stopping at the same
+    /// sample counts as the regular render would.
+    fn effective_progressive(&self) -> Option<Progressive> {
+        if self.progressive.is_some() || self.checkpoint.is_none() {
+            return self.progressive;
         }
+        let (samples_per_pass, target_samples) = match self.adaptive {
+            Some(adaptive) => (adaptive.batch_size, adaptive.max_samples),
+            None => (16, self.samples_per_pixel),
+        };
+        let mut progressive = Progressive::new(samples_per_pass);
+        progressive.target_samples = Some(target_samples);
+        progressive.write_interval = None;
+        Some(progressive)
+    }
+
+    /// Number of samples per pixel the sampler is set up for.
+    fn max_samples(&self, progressive: Option<&Progressive>) -> u32 {
+        match (progressive, self.adaptive) {
+            (Some(progressive), _) => progressive.target_samples.unwrap_or(self.samples_per_pixel),
+            (None, Some(adaptive)) => adaptive.max_samples,
+            (None, None) => self.samples_per_pixel,
+        }
+    }
+
+    /// `output_path` with `_noisy` appended to the file name.
+    fn noisy_output_path(&self) -> PathBuf {
+        let stem = self.output_path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.output_path.extension().map_or(String::new(), |e| format!(".{}", e.to_string_lossy()));
+        self.output_path.with_file_name(format!("{}_noisy{}", stem, extension))
+    }
+
+    /// `output_path` with `_heatmap` appended to the file name, always a PNG.
+    fn heatmap_path(&self) -> PathBuf {
+        let stem = self.output_path.file_stem().unwrap_or_default().to_string_lossy();
+        self.output_path.with_file_name(format!("{}_heatmap.png", stem))
+    }
+
+    /// Hash of everything that determines the rendered samples: the camera settings, the
+    /// punctual lights, a probe of the scene made by tracing a grid of primary rays and one of
+    /// the background in a grid of directions. Used to make sure a checkpoint is
+    /// only resumed with the scene and settings it was made with.
+    pub fn fingerprint(&self, scene: &Scene, progressive: Option<&Progressive>) -> u64 {
+        let (width, height) = self.film_size();
+        let mut values = vec![
+            width as u64,
+            height as u64,
+            self.seed,
+            self.sampler as u64,
+            self.max_samples(progressive) as u64,
+            self.max_depth as u64,
+            self.rr_min_bounces as u64,
+            self.mis_heuristic as u64,
+            self.samples_per_pixel as u64,
+            scene.punctual_lights.len() as u64,
+        ];
+        for light in &scene.punctual_lights {
+            values.extend(light.parameters().iter().map(|p| p.to_bits() as u64));
+        }
+        if let Some(adaptive) = self.adaptive {
+            values.extend([adaptive.min_samples, adaptive.max_samples, adaptive.error_threshold.to_bits(), adaptive.batch_size].map(u64::from));
+        }
+        if let Some(progressive) = progressive {
+            values.push(progressive.samples_per_pass as u64);
+        }
+        // Splatting sums samples into neighbouring pixels in the order the tiles are rendered
+        values.extend([self.filter.kind as u64, self.filter.radius.to_bits() as u64, self.tile_size as u64, self.tile_order as u64]);
+
+        let bits = |v: &Vec3| [v.x, v.y, v.z].map(|c| c.to_bits() as u64);
+        // Media sample their scattering distance randomly even for the probe rays
+        let mut rng = Rng::new(self.seed);
+        for j in 0..8 {
+            for i in 0..8 {
+                let ray = match self.get_ray_at((2 * i + 1) as f32 * width as f32 / 16.0, (2 * j + 1) as f32 * height as f32 / 16.0, (0.5, 0.5)) {
+                    Some((ray, _)) => ray,
+                    None => continue,
+                };
+                // The rays themselves stand in for the projection and the camera placement
+                values.extend(bits(&ray.origin()));
+                values.extend(bits(&ray.direction()));
+                let mut rec = HitRecord::new();
+                if scene.world.hit(&ray, &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut rng) {
+                    values.push(rec.t.to_bits() as u64);
+                    values.extend(bits(&rec.normal));
+                    values.extend(bits(&rec.material.emitted(&rec)));
+                    values.extend(bits(&rec.material.eval(&ray, &rec, &rec.normal)));
+                } else {
+                    values.extend(bits(&scene.background.color(&ray)));
+                }
+            }
+        }
+        // The background in every direction, including those the probe rays don't reach but
+        // bounces and light sampling do
+        for j in 0..8 {
+            let theta = (j as f32 + 0.5) * PI / 8.0;
+            for i in 0..16 {
+                let phi = (i as f32 + 0.5) * PI / 8.0;
+                let direction = Vec3::build(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                values.extend(bits(&scene.background.color(&Ray::new(&Point3::new(), &direction))));
+            }
+        }
+        hash_seed(&values)
+    }
+
+    /// Adds passes of samples to the whole image until one of the limits in `progressive` is
+    /// reached, starting after `pass` finished passes, and returns the number of finished
+    /// passes. `on_pass` is called with the image between two passes, an error it returns
+    /// stops the render. With adaptive sampling, pixels whose neighbourhood has converged are
+    /// skipped.
+    #[allow(clippy::too_many_arguments)]
+    fn render_progressive(&self, scene: &Scene, sampler: &mut dyn Sampler, framebuffer: &mut Framebuffer, progressive: &Progressive, mut pass: u32, observer: &mut dyn RenderObserver, cancel: &AtomicBool, mut on_pass: impl FnMut(&Framebuffer, u32) -> io::Result<()>) -> io::Result<u32> {
+        let start = Instant::now();
+        let samples_per_pass = progressive.samples_per_pass.max(1);
+        let has_limit = progressive.time_budget.is_some()
+            || progressive.target_samples.is_some()
+            || progressive.noise_threshold.is_some();
+        let target_samples = progressive.target_samples.or((!has_limit).then_some(self.samples_per_pixel));
+        let out_of_time = || progressive.time_budget.is_some_and(|budget| start.elapsed() >= budget);
+
+        let first_pass = pass;
+        loop {
+            // Decide which pixels to refine for the whole pass up front, like adaptive sampling
+            let mut counts = Vec::with_capacity((framebuffer.width * framebuffer.height) as usize);
+            for y in 0..framebuffer.height {
+                for x in 0..framebuffer.width {
+                    let count = framebuffer.pixel(x, y).count;
+                    let converged = self.adaptive.is_some_and(|adaptive| {
+                        count >= adaptive.min_samples
+                            && (count >= adaptive.max_samples || framebuffer.neighbourhood_error(x, y) <= adaptive.error_threshold)
+                    });
+                    let remaining = target_samples.map_or(samples_per_pass, |target| target.saturating_sub(count));
+                    counts.push(if converged { 0 } else { remaining.min(samples_per_pass) });
+                }
+            }
+            if counts.iter().all(|&count| count == 0) {
+                break;
+            }
+            // Only called when another pass follows, the caller handles the final image
+            if pass > first_pass {
+                on_pass(framebuffer, pass)?;
+            }
+
+            // Every pixel gets its first samples, after that the image is valid after any tile
+            let stop = || cancel.load(Ordering::Relaxed) || (pass > 0 && out_of_time());
+            if !self.sample_tiles(scene, sampler, framebuffer, &counts, stop, observer) {
+                break;
+            }
+            pass += 1;
+            observer.pass_finished(pass, framebuffer);
+
+            let error = framebuffer.mean_relative_error();
+            if out_of_time() || progressive.noise_threshold.is_some_and(|threshold| error <= threshold) {
+                break;
+            }
+        }
+        Ok(pass)
+    }
+
+    /// Gives every pixel `min_samples`, then adds batches of samples in passes over the pixels
+    /// that haven't converged yet. Returns the number of finished passes.
+    fn sample_adaptively(&self, scene: &Scene, sampler: &mut dyn Sampler, framebuffer: &mut Framebuffer, adaptive: &AdaptiveSampling, observer: &mut dyn RenderObserver, cancel: &AtomicBool) -> u32 {
+        let stop = || cancel.load(Ordering::Relaxed);
+        let min_samples = adaptive.min_samples.max(2).min(adaptive.max_samples);
+        let counts = vec![min_samples; (framebuffer.width * framebuffer.height) as usize];
+        if !self.sample_tiles(scene, sampler, framebuffer, &counts, stop, observer) {
+            return 0;
+        }
+        let mut pass = 1;
+        observer.pass_finished(pass, framebuffer);
+
+        loop {
+            // Decide for the whole pass up front, so the result doesn't depend on pixel order
+            let mut counts = Vec::with_capacity((framebuffer.width * framebuffer.height) as usize);
+            for y in 0..framebuffer.height {
+                for x in 0..framebuffer.width {
+                    let count = framebuffer.pixel(x, y).count;
+                    let converged = count >= adaptive.max_samples || framebuffer.neighbourhood_error(x, y) <= adaptive.error_threshold;
+                    counts.push(if converged { 0 } else { adaptive.batch_size.max(1).min(adaptive.max_samples - count) });
+                }
+            }
+            if counts.iter().all(|&count| count == 0) {
+                return pass;
+            }
+            if !self.sample_tiles(scene, sampler, framebuffer, &counts, stop, observer) {
+                return pass;
+            }
+            pass += 1;
+            observer.pass_finished(pass, framebuffer);
+        }
+    }
+
+    /// Adds `counts[y * width + x]` samples to every pixel, one tile after the other in
+    /// `tile_order`, and reports every tile that received samples to `observer`. `stop` is
+    /// asked before each tile; returns false if it ended the pass early.
+    fn sample_tiles(&self, scene: &Scene, sampler: &mut dyn Sampler, framebuffer: &mut Framebuffer, counts: &[u32], mut stop: impl FnMut() -> bool, observer: &mut dyn RenderObserver) -> bool {
+        for tile in tiles(framebuffer.width, framebuffer.height, self.tile_size, self.tile_order) {
+            if stop() {
+                return false;
+            }
+            let mut sampled = false;
+            for (x, y) in tile.pixels() {
+                let count = counts[(y * framebuffer.width + x) as usize];
+                if count > 0 {
+                    let (stats, aov, filtered) = framebuffer.sample_mut(x, y);
+                    self.sample_pixel(x, y, count, scene, sampler, stats, aov, |film_x, film_y, color| {
+                        filtered.splat(film_x, film_y, color, &self.filter);
+                    });
+                    sampled = true;
+                }
+            }
+            if sampled {
+                observer.tile_finished(&tile, framebuffer);
+            }
+        }
+        true
+    }
+
+
+    /// Adds `count` more samples of pixel (x, y) to `stats`. Every sample is seeded from the
+    /// render seed and its pixel and index only, so the result doesn't depend on the order
+    /// pixels are rendered in. The first hit of every sample is added to `aov`, if given, and
+    /// every sample is passed to `splat` with its position on the film.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_pixel(&self, x: u32, y: u32, count: u32, scene: &Scene, sampler: &mut dyn Sampler, stats: &mut PixelStats, mut aov: Option<&mut AovPixel>, mut splat: impl FnMut(f32, f32, &Color)) {
+        // For each pixel, we will sample multiple rays
+        // Both eyes of a stereo render share their samples, so noise doesn't differ between them
+        let (_, eye_x, eye_y) = self.eye_at(x as f32, y as f32);
+        let (eye_x, eye_y) = (eye_x as u32, eye_y as u32);
+        for sample in stats.count..stats.count + count {
+            sampler.start_pixel_sample(eye_x, eye_y, sample);
+            // Randomness that can't come from the sampler's fixed dimensions, like tracking
+            // through media, which takes a varying number of steps
+            let mut rng = Rng::new(hash_seed(&[self.seed, eye_x as u64, eye_y as u64, sample as u64, 1]));
+
+            let offset = self.sample_square(sampler);
+            // Only a lens with an aperture needs a point on it
+            let lens = if self.lens.is_some() || self.depth_of_field.is_some() { sampler.get_2d() } else { (0.5, 0.5) };
+            let mut first_hit = AovSample::new();
+            // Film positions outside a fisheye's image circle, and rays blocked inside a lens,
+            // stay black
+            let color = match self.get_ray(x, y, &offset, lens) {
+                Some((ray, weight)) => {
+                    stats::count(Counter::CameraRays);
+                    self.ray_color(&ray, scene, sampler, &mut rng, aov.is_some().then_some(&mut first_hit)) * weight
+                }
+                None => Color::new(),
+            };
+            if let Some(aov) = aov.as_deref_mut() {
+                aov.add(&first_hit);
+            }
+            stats.add(&color);
+            splat(x as f32 + 0.5 + offset.x, y as f32 + 0.5 + offset.y, &color);
+        }
+    }
+
+    /// Traces a path starting with `ray` and returns the radiance it carries back. The path is
+    /// followed iteratively, `throughput` is the product of all sample weights along it.
+    /// What the ray hits first is recorded in `first_hit`, if given.
+    fn ray_color(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler, rng: &mut Rng, mut first_hit: Option<&mut AovSample>) -> Color {
+        let mut radiance = Color::new();
+        let mut throughput = Color::build(1.0, 1.0, 1.0);
+        let mut ray = *ray;
+        // Density the last bounce sampled `ray` with, or `None` for camera rays and specular
+        // bounces, whose emission can't also be found by light sampling.
+        let mut bsdf_pdf: Option<f32> = None;
+
+        for bounce in 0..self.max_depth {
+            if bounce > 0 {
+                stats::count(Counter::SecondaryRays);
+            }
+            let mut rec = HitRecord::new();
+            let hit = scene.world.hit(&ray, &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, rng);
+            if let Some(aov) = first_hit.take() {
+                self.record_first_hit(aov, &ray, hit.then_some(&rec), scene);
+            }
+            if !hit {
+                radiance += throughput * self.weighted_background(&ray, scene, bsdf_pdf);
+                break;
+            }
+            stats::count(Counter::PathVertices);
+
+            radiance += throughput * self.weighted_emission(&ray, &rec, scene, bsdf_pdf);
 
-        let mut rec = HitRecord::new();
-        if world.hit(&ray, &Interval::with_bounds(0.001, f32::INFINITY), &mut rec) {
             // If the ray hit an object let the object's material scatter the ray,
             // this means modifying the ray's direction and color
-            let mut scattered = Ray::default();
-            let mut attenuation = Color::new();
-            if rec.material.scatter(ray, &rec, &mut attenuation, &mut scattered) {
-                // If the ray was scattered, recursively call ray_color
-                return attenuation * self.ray_color(&scattered, world, depth - 1);
+            let mut srec = ScatterRecord::new();
+            if !rec.material.scatter(&ray, &rec, &mut srec, sampler) {
+                break; // If the ray was not scattered, only the emitted light remains
+            }
+
+            if srec.is_specular {
+                bsdf_pdf = None;
+            } else {
+                let direct = self.sample_lights(&ray, &rec, scene, sampler, rng)
+                    + self.sample_punctual_lights(&ray, &rec, scene, sampler, rng);
+                radiance += throughput * direct;
+                bsdf_pdf = Some(srec.pdf);
+            }
+
+            throughput = throughput * srec.attenuation;
+            ray = srec.scattered;
+
+            // Russian roulette: continue with a probability based on how much the path can still
+            // contribute and compensate the survivors, so the estimate stays unbiased
+            if bounce + 1 >= self.rr_min_bounces {
+                let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        radiance
+    }
+
+    /// Fills `aov` with what the camera ray `ray` hit, `None` if it escaped to the background.
+    fn record_first_hit(&self, aov: &mut AovSample, ray: &Ray, rec: Option<&HitRecord>, scene: &Scene) {
+        let rec = match rec {
+            Some(rec) => rec,
+            None => {
+                aov.albedo = scene.background.color(ray);
+                return;
+            }
+        };
+        aov.hit = true;
+        aov.depth = if self.projection.is_planar() {
+            // Along the viewing direction, so flat surfaces facing the camera have the same depth
+            -Vec3::dot(&(rec.point - ray.origin()), &self.w)
+        } else {
+            (rec.point - ray.origin()).length()
+        };
+        aov.normal = if rec.front_face { rec.normal } else { -rec.normal };
+        aov.shading_normal = rec.normal;
+        aov.albedo = rec.material.albedo();
+        aov.material_id = rec.material.id();
+        aov.object_id = rec.object_id;
+        aov.uv = (rec.u, rec.v);
+        aov.position = rec.point;
+        // Where the hit point was seen in the previous frame, zero for a still image
+        let (width, height) = (self.image_width, self.image_height);
+        let previous = self.previous_view.unwrap_or(self.view()).project(&(rec.point - rec.motion), width, height);
+        if let (Some(now), Some(previous)) = (self.view().project(&rec.point, width, height), previous) {
+            aov.motion = (now.0 - previous.0, now.1 - previous.1);
+        }
+    }
+
+    /// Emission of the hit surface. When the ray was sampled from a BSDF the light could also
+    /// have been found by `sample_lights`, so it only gets its multiple importance sampling share.
+    fn weighted_emission(&self, ray: &Ray, rec: &HitRecord, scene: &Scene, bsdf_pdf: Option<f32>) -> Color {
+        let emitted = rec.material.emitted(rec);
+        match bsdf_pdf {
+            Some(pdf) if !emitted.near_zero() => {
+                let light_pdf = scene.light_pdf_value(&ray.origin(), &ray.direction());
+                emitted * self.mis_heuristic.weight(pdf, light_pdf)
+            }
+            _ => emitted,
+        }
+    }
+
+    /// Background seen by an escaping ray, weighted like `weighted_emission` when the
+    /// background is an environment map that `sample_lights` could also have found.
+    fn weighted_background(&self, ray: &Ray, scene: &Scene, bsdf_pdf: Option<f32>) -> Color {
+        let background = scene.background.color(ray);
+        match (bsdf_pdf, scene.background.environment()) {
+            (Some(pdf), Some(_)) => {
+                let light_pdf = scene.light_pdf_value(&ray.origin(), &ray.direction());
+                background * self.mis_heuristic.weight(pdf, light_pdf)
+            }
+            _ => background,
+        }
+    }
+
+    /// Next event estimation: picks a direction towards one of the scene's lights or the
+    /// environment map and returns its weighted contribution to the light leaving `rec`,
+    /// attenuated by whatever the shadow ray passes through on the way.
+    fn sample_lights(&self, r_in: &Ray, rec: &HitRecord, scene: &Scene, sampler: &mut dyn Sampler, rng: &mut Rng) -> Color {
+        if !scene.has_sampled_lights() {
+            return Color::new();
+        }
+
+        let direction = scene.sample_light_direction(&rec.point, sampler);
+        let pdf = scene.light_pdf_value(&rec.point, &direction);
+        if pdf <= 0.0 {
+            return Color::new();
+        }
+
+        let f = rec.material.eval(r_in, rec, &direction);
+        if f.near_zero() {
+            return Color::new();
+        }
+
+        // The shadow ray ends at the closest light it hits, or escapes to the environment map.
+        // Surfaces in between occlude the light, media let part of it through.
+        let shadow_ray = Ray::new(&rec.point, &direction);
+        let mut light_rec = HitRecord::new();
+        stats::count(Counter::ShadowRays);
+        let (radiance, distance) = if scene.lights.hit(&shadow_ray, &Interval::with_bounds(0.001, f32::INFINITY), &mut light_rec, rng) {
+            (light_rec.material.emitted(&light_rec), light_rec.t - 0.001)
+        } else {
+            match scene.background.environment() {
+                Some(env) => (env.radiance(&direction), f32::INFINITY),
+                None => return Color::new(),
+            }
+        };
+        if radiance.near_zero() {
+            return Color::new();
+        }
+        let transmittance = scene.world.transmittance(&shadow_ray, &Interval::with_bounds(0.001, distance), rng);
+        if transmittance <= 0.0 {
+            return Color::new();
+        }
+
+        let weight = self.mis_heuristic.weight(pdf, rec.material.pdf(r_in, rec, &direction));
+        f * radiance * (transmittance * weight / pdf)
+    }
+
+    /// Direct light from every punctual light, attenuated by what lies in between. These can't
+    /// be hit by scattered rays, so no weighting against BSDF samples is needed.
+    fn sample_punctual_lights(&self, r_in: &Ray, rec: &HitRecord, scene: &Scene, sampler: &mut dyn Sampler, rng: &mut Rng) -> Color {
+        let mut direct = Color::new();
+
+        for light in &scene.punctual_lights {
+            let sample = match light.sample(&rec.point, sampler) {
+                Some(sample) => sample,
+                None => continue,
+            };
+
+            let f = rec.material.eval(r_in, rec, &sample.direction);
+            if f.near_zero() {
+                continue;
+            }
+
+            let shadow_ray = Ray::new(&rec.point, &sample.direction);
+            stats::count(Counter::ShadowRays);
+            let transmittance = scene.world.transmittance(&shadow_ray, &Interval::with_bounds(0.001, sample.distance - 0.001), rng);
+            direct += f * sample.radiance * transmittance;
+        }
+
+        direct
+    }
+
+    /// Camera ray through pixel (i, j), `offset` from the pixel's center, and the weight of
+    /// the radiance it brings back. `lens` picks the point on the lens. `None` where the
+    /// projection doesn't cover the film or the lens blocks the ray.
+    fn get_ray(&self, i: u32, j: u32, offset: &Vec3, lens: (f32, f32)) -> Option<(Ray, f32)> {
+        self.get_ray_at(i as f32 + 0.5 + offset.x, j as f32 + 0.5 + offset.y, lens)
+    }
+
+    /// Camera ray through the film position `x`, `y` in pixels, see `get_ray`.
+    fn get_ray_at(&self, x: f32, y: f32, lens: (f32, f32)) -> Option<(Ray, f32)> {
+        let (side, x, y) = self.eye_at(x, y);
+        let (mut offset, mut direction, weight) = match &self.lens {
+            Some(realistic) => realistic.ray(x, y, self.image_width, self.image_height, lens)?,
+            None => {
+                let (mut offset, mut direction) = self.projection.ray(x, y, self.image_width, self.image_height)?;
+                if let Some(depth_of_field) = self.depth_of_field.as_ref().filter(|_| self.projection.is_planar()) {
+                    let (width, height) = (self.image_width as f32, self.image_height as f32);
+                    let diagonal = (width * width + height * height).sqrt();
+                    let film = ((2.0 * x - width) / diagonal, (height - 2.0 * y) / diagonal);
+                    (offset, direction) = depth_of_field.ray(offset, direction, film, lens)?;
+                }
+                (offset, direction, 1.0)
+            }
+        };
+        if let Some(stereo) = &self.stereo {
+            let planar = self.lens.is_some() || self.projection.is_planar();
+            (offset, direction) = stereo.eye_ray(side, planar, offset, direction);
+        }
+        let to_world = |c: &Vec3| self.u * c.x + self.v * c.y + self.w * c.z;
+        Some((Ray::new(&(self.origin + to_world(&offset)), &to_world(&direction)), weight))
+    }
+
+    fn sample_square(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        Vec3::build(u - 0.5, v - 0.5, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use crate::progress::Silent;
+    use crate::filter::FilterKind;
+    use crate::tile::Tile;
+    use crate::constant_medium::ConstantMedium;
+    use crate::material::{DiffuseLight, Isotropic, Lambertian, Material};
+    use crate::light::{Light, PointLight};
+    use crate::quad::{make_box, Quad};
+    use crate::scene::Background;
+    use crate::sampler::sample_uniform_sphere;
+    use crate::sphere::Sphere;
+
+    fn foggy_scene() -> Scene {
+        let mut scene = Scene::new();
+        let ground = Material::Lambertian(Lambertian::new(Color::build(0.5, 0.5, 0.5)));
+        scene.add(Rc::new(Sphere::new(&Point3::build(0.0, -100.5, -1.0), 100.0, ground)));
+        let fog = Material::Isotropic(Isotropic::new(Color::build(0.9, 0.9, 0.9)));
+        let boundary = Rc::new(Sphere::new(&Point3::build(0.0, 0.0, -1.0), 0.5, fog));
+        scene.add(Rc::new(ConstantMedium::new(boundary, 2.0, fog)));
+        scene
+    }
+
+    /// Records the events of a render, optionally cancelling it after the first tile.
+    #[derive(Default)]
+    struct Recorder {
+        tiles: Vec<Tile>,
+        passes: Vec<u32>,
+        cancelled: bool,
+        completed: bool,
+        cancel: Option<Arc<AtomicBool>>,
+    }
+
+    impl RenderObserver for Recorder {
+        fn tile_finished(&mut self, tile: &Tile, framebuffer: &Framebuffer) {
+            assert!(tile.pixels().all(|(x, y)| framebuffer.pixel(x, y).count > 0));
+            self.tiles.push(*tile);
+            if let Some(cancel) = &self.cancel {
+                cancel.store(true, Ordering::Relaxed);
             }
-            return Color::new(); // If the ray was not scattered, return black
         }
 
-        let unit_direction = Vec3::normalize(&ray.direction());
-        let a = 0.5*(unit_direction.y() + 1.0);
-        Color::build(1.0, 1.0, 1.0) * (1.0 - a) + Color::build(0.5, 0.7, 1.0) * a
+        fn pass_finished(&mut self, pass: u32, _framebuffer: &Framebuffer) {
+            self.passes.push(pass);
+        }
+
+        fn cancelled(&mut self, _framebuffer: &Framebuffer) {
+            self.cancelled = true;
+        }
+
+        fn completed(&mut self, _framebuffer: &Framebuffer, _summary: &RenderSummary) {
+            self.completed = true;
+        }
     }
 
-    // Returns a ray from the camera to a randomly sampled point
-    // around pixel at (i, j) 
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let offset = self.sample_square();
-        let pixel_sample = self.pixel_00_loc + 
-            ( self.pixel_delta_u * (i as f32 + offset.x) ) +
-            ( self.pixel_delta_v * (j as f32 + offset.y) );
+    fn render_pixels(camera: &Camera, scene: &Scene, pixels: &[(u32, u32)]) -> Vec<PixelStats> {
+        let mut sampler = camera.sampler.create(camera.samples_per_pixel, camera.seed);
+        pixels
+            .iter()
+            .map(|&(x, y)| {
+                let mut stats = PixelStats::new();
+                camera.sample_pixel(x, y, camera.samples_per_pixel, scene, sampler.as_mut(), &mut stats, None, |_, _, _| {});
+                stats
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_same_seed_renders_identically() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 16);
+        camera.samples_per_pixel = 8;
+        camera.sampler = SamplerType::Independent;
+        camera.seed = 7;
 
-        let ray_origin = self.origin;
-        let ray_direction = pixel_sample - ray_origin;
+        let pixels = [(8, 8), (8, 10), (3, 12)];
+        let first = render_pixels(&camera, &scene, &pixels);
+        // Rendering in a different order gives the same pixels
+        let mut reversed = render_pixels(&camera, &scene, &[(3, 12), (8, 10), (8, 8)]);
+        reversed.reverse();
+        assert_eq!(first, reversed);
 
-        Ray::new(&ray_origin, &ray_direction)
+        camera.seed = 8;
+        assert_ne!(first, render_pixels(&camera, &scene, &pixels));
     }
 
-    fn sample_square(&self) -> Vec3 {
-        Vec3::build(random_float() - 0.5, random_float() - 0.5, 0.0)
+    #[test]
+    fn test_adaptive_sampling_skips_converged_pixels() {
+        let mut scene = foggy_scene();
+        scene.background = Background::Solid(Color::build(0.5, 0.7, 1.0));
+        let camera = Camera::new(1.0, 16);
+        let mut adaptive = AdaptiveSampling::new(0.02);
+        adaptive.max_samples = 128;
+
+        let mut framebuffer = Framebuffer::new(16, 16);
+        let mut sampler = camera.sampler.create(adaptive.max_samples, camera.seed);
+        camera.sample_adaptively(&scene, sampler.as_mut(), &mut framebuffer, &adaptive, &mut Silent, &AtomicBool::new(false));
+
+        // The corner only sees the flat background, the center looks into the fog
+        assert_eq!(framebuffer.pixel(0, 0).count, adaptive.min_samples);
+        assert!(framebuffer.pixel(8, 8).count > adaptive.min_samples);
+        assert!(framebuffer.pixel(8, 8).count <= adaptive.max_samples);
+    }
+
+    #[test]
+    fn test_progressive_stops_at_target_samples() {
+        let scene = foggy_scene();
+        let camera = Camera::new(1.0, 4);
+        let mut progressive = Progressive::new(3);
+        progressive.target_samples = Some(7);
+        progressive.write_interval = Some(Duration::ZERO);
+
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let mut sampler = camera.sampler.create(7, camera.seed);
+        let mut passes = Vec::new();
+        let pass = camera.render_progressive(&scene, sampler.as_mut(), &mut framebuffer, &progressive, 0, &mut Silent, &AtomicBool::new(false), |_, pass| {
+            passes.push(pass);
+            Ok(())
+        }).unwrap();
+
+        assert!((0..4).all(|y| (0..4).all(|x| framebuffer.pixel(x, y).count == 7)));
+        // Passes of 3, 3 and 1 samples, reported between passes only
+        assert_eq!(pass, 3);
+        assert_eq!(passes, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_progressive_time_budget_finishes_first_pass() {
+        let scene = foggy_scene();
+        let camera = Camera::new(1.0, 4);
+        let mut progressive = Progressive::new(2);
+        progressive.time_budget = Some(Duration::ZERO);
+
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let mut sampler = camera.sampler.create(2, camera.seed);
+        camera.render_progressive(&scene, sampler.as_mut(), &mut framebuffer, &progressive, 0, &mut Silent, &AtomicBool::new(false), |_, _| Ok(())).unwrap();
+
+        assert!((0..4).all(|y| (0..4).all(|x| framebuffer.pixel(x, y).count == 2)));
+    }
+
+    #[test]
+    fn test_resumed_render_matches_uninterrupted() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 4);
+        camera.filter = PixelFilter::new(FilterKind::Mitchell);
+        camera.tile_size = 2;
+        let mut progressive = Progressive::new(2);
+        progressive.target_samples = Some(6);
+
+        let mut sampler = camera.sampler.create(6, camera.seed);
+        let mut full = Framebuffer::new(4, 4);
+        let mut saved = None;
+        camera.render_progressive(&scene, sampler.as_mut(), &mut full, &progressive, 0, &mut Silent, &AtomicBool::new(false), |framebuffer, pass| {
+            if pass == 1 {
+                let checkpoint = Checkpoint { fingerprint: 0, seed: camera.seed, pass, framebuffer: framebuffer.clone() };
+                saved = Some(checkpoint.to_bytes());
+            }
+            Ok(())
+        }).unwrap();
+
+        let checkpoint = Checkpoint::from_bytes(&saved.unwrap()).unwrap();
+        let mut resumed = checkpoint.framebuffer;
+        let mut sampler = camera.sampler.create(6, camera.seed);
+        camera.render_progressive(&scene, sampler.as_mut(), &mut resumed, &progressive, checkpoint.pass, &mut Silent, &AtomicBool::new(false), |_, _| Ok(())).unwrap();
+        assert_eq!(resumed.pixels(), full.pixels());
+        assert_eq!(resumed.filtered.pixels(), full.filtered.pixels());
+    }
+
+    #[test]
+    fn test_tiles_are_reported_once_when_finished() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 20);
+        camera.samples_per_pixel = 2;
+        camera.tile_size = 8;
+        camera.tile_order = TileOrder::Hilbert;
+
+        let mut framebuffer = Framebuffer::new(20, 20);
+        let mut sampler = camera.sampler.create(2, camera.seed);
+        let counts = vec![2; 400];
+        let mut recorder = Recorder::default();
+        camera.sample_tiles(&scene, sampler.as_mut(), &mut framebuffer, &counts, || false, &mut recorder);
+        assert_eq!(recorder.tiles, tiles(20, 20, 8, TileOrder::Hilbert));
+
+        // Tiles without any samples to add aren't reported
+        let mut counts = vec![0; 400];
+        counts[19 * 20 + 19] = 1;
+        let mut recorder = Recorder::default();
+        camera.sample_tiles(&scene, sampler.as_mut(), &mut framebuffer, &counts, || false, &mut recorder);
+        assert_eq!(recorder.tiles, vec![Tile { x0: 16, y0: 16, x1: 20, y1: 20 }]);
+    }
+
+    #[test]
+    fn test_cancelled_render_stops_after_tile() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 32);
+        camera.samples_per_pixel = 1;
+        camera.tile_size = 8;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut recorder = Recorder { cancel: Some(cancel.clone()), ..Recorder::default() };
+        camera.render_with(&scene, &mut recorder, &cancel).unwrap();
+
+        assert_eq!(recorder.tiles.len(), 1);
+        assert!(recorder.passes.is_empty());
+        assert!(recorder.cancelled && !recorder.completed);
+    }
+
+    #[test]
+    fn test_aovs_record_first_hit_without_changing_the_image() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 16);
+        camera.samples_per_pixel = 4;
+        let mut sampler = camera.sampler.create(4, camera.seed);
+
+        let mut stats = PixelStats::new();
+        let mut aov = AovPixel::new();
+        camera.sample_pixel(8, 15, 4, &scene, sampler.as_mut(), &mut stats, Some(&mut aov), |_, _, _| {});
+        assert_eq!(vec![stats], render_pixels(&camera, &scene, &[(8, 15)]));
+
+        // The bottom row looks down at the ground sphere, the first object of the scene
+        assert_eq!(aov.value(Aov::ObjectId), [1.0; 3]);
+        let normal = aov.value(Aov::Normal);
+        assert!(normal[1] > 0.9);
+        assert!(aov.value(Aov::Depth)[0] > 0.0);
+        assert!((aov.value(Aov::Albedo)[0] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_motion_follows_the_camera() {
+        let scene = Scene::new();
+        let mut camera = Camera::new(1.0, 16);
+        let ray = Ray::new(&Point3::new(), &Vec3::build(0.0, 0.0, -1.0));
+        let motion = |camera: &Camera, point: Point3| {
+            let mut rec = HitRecord::new();
+            rec.point = point;
+            let mut aov = AovSample::new();
+            camera.record_first_hit(&mut aov, &ray, Some(&rec), &scene);
+            aov.motion
+        };
+        let ahead = Point3::build(0.0, 0.0, -3.0);
+        assert_eq!(motion(&camera, ahead), (0.0, 0.0));
+
+        // Moving half a unit to the right shifts the point 3 units ahead by a sixth of the
+        // half width of the 90° view
+        camera.mark_previous_frame();
+        camera.look_at(Point3::build(0.5, 0.0, 0.0), Point3::build(0.5, 0.0, -1.0), Vec3::build(0.0, 1.0, 0.0));
+        let (dx, dy) = motion(&camera, ahead);
+        assert!((dx + 8.0 / 6.0).abs() < 1e-4 && dy.abs() < 1e-4, "{} {}", dx, dy);
+
+        // Zooming in pushes points outwards from the center
+        camera.look_at(Point3::new(), Point3::build(0.0, 0.0, -1.0), Vec3::build(0.0, 1.0, 0.0));
+        camera.mark_previous_frame();
+        camera.projection = Projection::Perspective { vfov: 60.0 };
+        let (dx, dy) = motion(&camera, Point3::build(1.0, 1.0, -3.0));
+        assert!(dx > 0.0 && dy < 0.0);
+    }
+
+    #[test]
+    fn test_look_at_orients_projections() {
+        let mut camera = Camera::new(1.0, 8);
+        let center = |camera: &Camera| camera.get_ray_at(4.0, 4.0, (0.5, 0.5)).unwrap().0;
+        assert_eq!(Vec3::normalize(&center(&camera).direction()), Vec3::build(0.0, 0.0, -1.0));
+
+        camera.look_at(Point3::build(1.0, 2.0, 3.0), Point3::build(1.0, 2.0, 5.0), Vec3::build(0.0, 1.0, 0.0));
+        for projection in [Projection::Orthographic { height: 1.0 }, Projection::FisheyeEquisolid { fov: 180.0 }, Projection::Equirectangular] {
+            camera.projection = projection;
+            let ray = center(&camera);
+            assert!((ray.origin() - Point3::build(1.0, 2.0, 3.0)).length() < 1e-5);
+            assert!((Vec3::normalize(&ray.direction()) - Vec3::build(0.0, 0.0, 1.0)).length() < 1e-5);
+        }
+        // The top of the image is still up
+        let top = camera.get_ray_at(4.0, 0.0, (0.5, 0.5)).unwrap().0;
+        assert!((Vec3::normalize(&top.direction()) - Vec3::build(0.0, 1.0, 0.0)).length() < 1e-5);
+
+        // Outside the fisheye's image circle nothing is traced
+        camera.projection = Projection::FisheyeEquidistant { fov: 180.0 };
+        assert!(camera.get_ray(0, 0, &Vec3::new(), (0.5, 0.5)).is_none());
+        assert_eq!(render_pixels(&camera, &foggy_scene(), &[(0, 0)])[0].mean, Color::new());
+    }
+
+    #[test]
+    fn test_stereo_eyes_share_samples() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 8);
+        camera.samples_per_pixel = 4;
+        let mono = render_pixels(&camera, &scene, &[(3, 5)]);
+
+        // Without any separation both eyes see exactly what the single camera sees
+        let mut stereo = Stereo::new(StereoLayout::SideBySide);
+        stereo.interocular = 0.0;
+        camera.stereo = Some(stereo);
+        assert_eq!(camera.film_size(), (16, 8));
+        assert_eq!(render_pixels(&camera, &scene, &[(3, 5), (11, 5)]), vec![mono[0], mono[0]]);
+
+        stereo.layout = StereoLayout::OverUnder;
+        camera.stereo = Some(stereo);
+        assert_eq!(camera.film_size(), (8, 16));
+        assert_eq!(render_pixels(&camera, &scene, &[(3, 13)]), mono);
+    }
+
+    #[test]
+    fn test_stereo_eyes_converge() {
+        let mut camera = Camera::new(1.0, 8);
+        let mut stereo = Stereo::new(StereoLayout::SideBySide);
+        stereo.interocular = 0.1;
+        stereo.convergence = 2.0;
+        camera.stereo = Some(stereo);
+
+        let (left, right) = (camera.get_ray_at(4.0, 4.0, (0.5, 0.5)).unwrap().0, camera.get_ray_at(12.0, 4.0, (0.5, 0.5)).unwrap().0);
+        assert!((left.origin() - Point3::build(-0.05, 0.0, 0.0)).length() < 1e-6);
+        assert!((right.origin() - Point3::build(0.05, 0.0, 0.0)).length() < 1e-6);
+        let on_convergence_plane = |ray: &Ray| ray.at(2.0 / -ray.direction().z);
+        assert!((on_convergence_plane(&left) - Point3::build(0.0, 0.0, -2.0)).length() < 1e-5);
+        assert!((on_convergence_plane(&right) - Point3::build(0.0, 0.0, -2.0)).length() < 1e-5);
+
+        // In a panorama the right eye is behind the viewer's center when looking right
+        stereo.convergence = f32::INFINITY;
+        camera.stereo = Some(stereo);
+        camera.projection = Projection::Equirectangular;
+        let right = camera.get_ray_at(14.0, 4.0, (0.5, 0.5)).unwrap().0;
+        assert!((right.origin() - Point3::build(0.0, 0.0, 0.05)).length() < 1e-6);
+        assert!((Vec3::normalize(&right.direction()) - Vec3::build(1.0, 0.0, 0.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_realistic_lens_replaces_projection() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 8);
+        camera.samples_per_pixel = 4;
+        let pinhole = camera.fingerprint(&scene, None);
+
+        let mut lens = RealisticLens::new(RealisticLens::parse(include_str!("../lenses/double_gauss_50mm.txt")).unwrap(), 43.27);
+        assert!(lens.focus(1.0));
+        camera.lens = Some(lens);
+        let (ray, weight) = camera.get_ray_at(4.0, 4.0, (0.5, 0.5)).unwrap();
+        // Leaving the front of the lens, a few centimetres in front of the film
+        assert!(ray.origin().z < -0.02 && ray.origin().z > -0.2);
+        assert!(Vec3::normalize(&ray.direction()).z < -0.99);
+        assert!(weight > 0.5);
+
+        assert_ne!(camera.fingerprint(&scene, None), pinhole);
+        let pixels = render_pixels(&camera, &scene, &[(4, 4), (0, 7)]);
+        assert_eq!(pixels, render_pixels(&camera, &scene, &[(4, 4), (0, 7)]));
+        assert!(pixels[0].mean.length() > 0.0);
+    }
+
+    #[test]
+    fn test_depth_of_field_focuses_on_plane() {
+        let mut camera = Camera::new(1.0, 8);
+        let mut depth_of_field = DepthOfField::new(0.1, 3.0);
+        depth_of_field.aperture = Aperture::Polygon { blades: 6, rotation: 0.0 };
+        camera.depth_of_field = Some(depth_of_field.clone());
+
+        // Rays through the same film position start apart but meet on the focus plane
+        let pinhole = Ray::new(&Point3::new(), &Vec3::build(-0.5, 0.5, -1.0));
+        for lens in [(0.1, 0.2), (0.45, 0.9), (0.8, 0.3)] {
+            let (ray, _) = camera.get_ray_at(2.0, 2.0, lens).unwrap();
+            assert!(ray.origin().length() > 0.0 && ray.origin().length() <= 0.1);
+            let on_focus_plane = ray.at((-3.0 - ray.origin().z) / ray.direction().z);
+            assert!((on_focus_plane - pinhole.at(3.0)).length() < 1e-5);
+        }
+
+        // The lens barrel blocks part of the aperture only toward the corners
+        depth_of_field.aperture = Aperture::Circle;
+        depth_of_field.cat_eye = 1.0;
+        camera.depth_of_field = Some(depth_of_field);
+        let blocked = |x: f32, y: f32| (0..10).filter(|&i| camera.get_ray_at(x, y, (i as f32 / 10.0 + 0.05, 0.5)).is_none()).count();
+        assert_eq!(blocked(4.0, 4.0), 0);
+        assert!(blocked(0.0, 4.0) > 0);
+        assert!(blocked(0.0, 0.0) > blocked(0.0, 4.0));
+    }
+
+    #[test]
+    fn test_shadow_rays_pass_through_media() {
+        let camera = Camera::new(1.0, 8);
+        let white = Material::Lambertian(Lambertian::new(Color::build(1.0, 1.0, 1.0)));
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::build(0.0, 1.0, 0.0);
+        rec.front_face = true;
+        rec.material = white;
+        let r_in = Ray::new(&Point3::build(0.0, 1.0, 1.0), &Vec3::build(0.0, -1.0, -1.0));
+        let direct = |scene: &Scene| {
+            let mut sampler = camera.sampler.create(1, 0);
+            sampler.start_pixel_sample(0, 0, 0);
+            let mut rng = Rng::new(0);
+            camera.sample_lights(&r_in, &rec, scene, sampler.as_mut(), &mut rng)
+                + camera.sample_punctual_lights(&r_in, &rec, scene, sampler.as_mut(), &mut rng)
+        };
+        // A slab of fog one unit thick between the shaded point and the lights above
+        let fog = || {
+            let slab = make_box(&Point3::build(-10.0, 1.0, -10.0), &Point3::build(10.0, 2.0, 10.0), white);
+            Rc::new(ConstantMedium::new(Rc::new(slab), 0.7, Material::Isotropic(Isotropic::new(Color::new()))))
+        };
+        let expected = (-0.7f32).exp();
+
+        let mut punctual = Scene::new();
+        punctual.add_punctual_light(Light::Point(PointLight::new(Point3::build(0.0, 3.0, 0.0), Color::build(9.0, 9.0, 9.0))));
+        let clear = direct(&punctual);
+        punctual.add(fog());
+        assert!((direct(&punctual).x / clear.x - expected).abs() < 1e-5);
+
+        // A tiny area light straight above, so every shadow ray crosses the slab vertically
+        let mut area = Scene::new();
+        let light = Material::DiffuseLight(DiffuseLight::new(Color::build(1.0, 1.0, 1.0)));
+        area.add_light(Rc::new(Quad::new(&Point3::build(-0.005, 3.0, -0.005), &Vec3::build(0.01, 0.0, 0.0), &Vec3::build(0.0, 0.0, 0.01), light)));
+        let clear = direct(&area);
+        assert!(clear.x > 0.0);
+        area.add(fog());
+        assert!((direct(&area).x / clear.x - expected).abs() < 1e-4);
+
+        // Surfaces still block the light completely
+        area.add(Rc::new(Quad::new(&Point3::build(-1.0, 2.5, -1.0), &Vec3::build(2.0, 0.0, 0.0), &Vec3::build(0.0, 0.0, 2.0), white)));
+        assert_eq!(direct(&area), Color::new());
+    }
+
+    /// Closed box around the origin with grey walls, lit by its whole ceiling.
+    fn lit_box(albedo: f32) -> Scene {
+        let mut scene = Scene::new();
+        let walls = Material::Lambertian(Lambertian::new(Color::build(albedo, albedo, albedo)));
+        scene.add(Rc::new(make_box(&Point3::build(-1.0, -1.0, -1.0), &Point3::build(1.0, 1.0, 1.0), walls)));
+        let light = Material::DiffuseLight(DiffuseLight::new(Color::build(1.0, 1.0, 1.0)));
+        scene.add_light(Rc::new(Quad::new(&Point3::build(-1.0, 0.99, -1.0), &Vec3::build(2.0, 0.0, 0.0), &Vec3::build(0.0, 0.0, 2.0), light)));
+        scene
+    }
+
+    #[test]
+    fn test_russian_roulette_keeps_the_mean() {
+        let scene = lit_box(0.6);
+        let mean = |rr_min_bounces: u32| {
+            let mut camera = Camera::new(1.0, 8);
+            // Long enough that cutting paths off doesn't bias the result
+            camera.max_depth = 64;
+            camera.rr_min_bounces = rr_min_bounces;
+            let n = 20000;
+            let mut sampler = camera.sampler.create(n, 0);
+            let mut sum = 0.0;
+            for i in 0..n {
+                sampler.start_pixel_sample(0, 0, i);
+                let direction = sample_uniform_sphere(sampler.get_2d());
+                let ray = Ray::new(&Point3::new(), &direction);
+                sum += camera.ray_color(&ray, &scene, sampler.as_mut(), &mut Rng::new(i as u64), None).y;
+            }
+            sum / n as f32
+        };
+        let without = mean(64);
+        let with = mean(1);
+        assert!(without > 0.3);
+        assert!((with / without - 1.0).abs() < 0.02, "{} {}", with, without);
+    }
+
+    #[test]
+    fn test_deep_paths_do_not_recurse() {
+        // Inside a white sphere without roulette every path bounces until `max_depth`. On a
+        // small stack, recursing once per bounce would overflow long before.
+        let vertices = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let mut scene = Scene::new();
+                let white = Material::Lambertian(Lambertian::new(Color::build(1.0, 1.0, 1.0)));
+                scene.add(Rc::new(Sphere::new(&Point3::new(), 10.0, white)));
+                let mut camera = Camera::new(1.0, 8);
+                camera.max_depth = 10_000;
+                camera.rr_min_bounces = u32::MAX;
+                let mut sampler = camera.sampler.create(1, 0);
+                sampler.start_pixel_sample(0, 0, 0);
+                let ray = Ray::new(&Point3::new(), &Vec3::build(0.3, 0.2, 1.0));
+                let color = camera.ray_color(&ray, &scene, sampler.as_mut(), &mut Rng::new(0), None);
+                assert_eq!(color, Color::new());
+                stats::take().get(Counter::PathVertices)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(vertices, 10_000);
+    }
+
+    #[test]
+    fn test_side_outputs_are_named_after_the_output() {
+        let mut camera = Camera::new(1.0, 8);
+        camera.output_path = PathBuf::from("frames/frame_0007.exr");
+        assert_eq!(camera.noisy_output_path(), PathBuf::from("frames/frame_0007_noisy.exr"));
+        assert_eq!(camera.heatmap_path(), PathBuf::from("frames/frame_0007_heatmap.png"));
+    }
+
+    #[test]
+    fn test_fingerprint_detects_changes() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 8);
+        let fingerprint = camera.fingerprint(&scene, None);
+        assert_eq!(fingerprint, camera.fingerprint(&foggy_scene(), None));
+
+        let mut other_scene = foggy_scene();
+        other_scene.add(Rc::new(Sphere::new(&Point3::build(0.3, 0.2, -0.8), 0.2, Material::Isotropic(Isotropic::new(Color::new())))));
+        assert_ne!(fingerprint, camera.fingerprint(&other_scene, None));
+
+        camera.seed = 1;
+        assert_ne!(fingerprint, camera.fingerprint(&scene, None));
+        camera.seed = 0;
+
+        // Lights and the background the probe rays don't see
+        let light = |position| Light::Point(PointLight::new(position, Color::build(4.0, 4.0, 4.0)));
+        let mut lit = foggy_scene();
+        lit.add_punctual_light(light(Point3::build(0.0, 2.0, 0.0)));
+        let lit_fingerprint = camera.fingerprint(&lit, None);
+        assert_ne!(fingerprint, lit_fingerprint);
+        let mut moved = foggy_scene();
+        moved.add_punctual_light(light(Point3::build(0.0, 2.5, 0.0)));
+        assert_ne!(lit_fingerprint, camera.fingerprint(&moved, None));
+        let mut dark = foggy_scene();
+        dark.background = Background::Solid(Color::new());
+        assert_ne!(fingerprint, camera.fingerprint(&dark, None));
+
+        camera.samples_per_pixel += 1;
+        assert_ne!(fingerprint, camera.fingerprint(&scene, None));
+    }
+
+    #[test]
+    fn test_failed_writes_are_errors() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 4);
+        camera.samples_per_pixel = 1;
+        let missing = std::env::temp_dir().join(format!("raytracing_test_missing_{}", std::process::id()));
+        camera.output_path = missing.join("image.png");
+        assert!(camera.render_with(&scene, &mut Silent, &AtomicBool::new(false)).is_err());
+
+        // Checkpoints are written before the image
+        camera.output_path = PathBuf::from(OUTPUT_PATH);
+        camera.checkpoint = Some(Checkpointing::new(missing.join("checkpoint.bin")));
+        assert!(camera.render_with(&scene, &mut Silent, &AtomicBool::new(false)).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_unusable_checkpoints_are_errors() {
+        let scene = foggy_scene();
+        let mut camera = Camera::new(1.0, 4);
+        let path = std::env::temp_dir().join(format!("raytracing_test_checkpoint_{}.bin", std::process::id()));
+        let mut checkpointing = Checkpointing::new(&path);
+        checkpointing.resume = true;
+        camera.checkpoint = Some(checkpointing);
+
+        // Made with different settings
+        let checkpoint = Checkpoint { fingerprint: 1, seed: camera.seed, pass: 1, framebuffer: Framebuffer::new(4, 4) };
+        checkpoint.save(&path).unwrap();
+        let mut recorder = Recorder::default();
+        let error = camera.render_with(&scene, &mut recorder, &AtomicBool::new(false)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(recorder.tiles.is_empty() && !recorder.completed);
+
+        std::fs::write(&path, b"not a checkpoint").unwrap();
+        assert!(camera.render_with(&scene, &mut Recorder::default(), &AtomicBool::new(false)).is_err());
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::framebuffer::{FilteredPixel, Framebuffer, PixelStats};
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 2;
+const HEADER_BYTES: usize = 4 + 4 + 8 + 8 + 4 + 4 + 4;
+
+/// Everything needed to continue an interrupted progressive render. Every sample is seeded
+/// from the render seed, its pixel and its index, so the seed together with the per-pixel
+/// sample counts is the complete random number state.
+pub struct Checkpoint {
+    /// Hash of the scene and the camera settings that influence the result, see
+    /// `Camera::fingerprint`.
+    pub fingerprint: u64,
+    pub seed: u64,
+    /// Number of finished passes.
+    pub pass: u32,
+    pub framebuffer: Framebuffer,
+}
+
+impl Checkpoint {
+    /// Loads a checkpoint from its binary format: the magic `RTCK`, then little endian
+    /// version (`u32`), fingerprint (`u64`), seed (`u64`), pass, width and height (`u32`),
+    /// followed by `PixelStats::BYTES` for every pixel row by row and then
+    /// `FilteredPixel::BYTES` for every pixel of the filtered image.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if bytes.len() < HEADER_BYTES || &bytes[0..4] != MAGIC {
+            return Err(invalid("not a render checkpoint"));
+        }
+        let read_u32 = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        if read_u32(4) != VERSION {
+            return Err(invalid("unsupported checkpoint version"));
+        }
+        let fingerprint = read_u64(8);
+        let seed = read_u64(16);
+        let pass = read_u32(24);
+        let (width, height) = (read_u32(28), read_u32(32));
+
+        let count = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or_else(|| invalid("checkpoint resolution overflows"))?;
+        let body = &bytes[HEADER_BYTES..];
+        if body.len() != count * (PixelStats::BYTES + FilteredPixel::BYTES) {
+            return Err(invalid("checkpoint data does not match its resolution"));
+        }
+
+        let (stats, filtered) = body.split_at(count * PixelStats::BYTES);
+        let pixels = stats.chunks_exact(PixelStats::BYTES).map(PixelStats::from_le_bytes).collect();
+        let filtered = filtered.chunks_exact(FilteredPixel::BYTES).map(FilteredPixel::from_le_bytes).collect();
+        Ok(Self {
+            fingerprint,
+            seed,
+            pass,
+            framebuffer: Framebuffer::from_pixels(width, height, pixels, filtered),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let pixels = self.framebuffer.pixels();
+        let mut bytes = Vec::with_capacity(HEADER_BYTES + pixels.len() * (PixelStats::BYTES + FilteredPixel::BYTES));
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.fingerprint.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        for v in [self.pass, self.framebuffer.width, self.framebuffer.height] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        for pixel in pixels {
+            bytes.extend_from_slice(&pixel.to_le_bytes());
+        }
+        for pixel in self.framebuffer.filtered.pixels() {
+            bytes.extend_from_slice(&pixel.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Writes to a temporary file first and renames it, so a crash while saving never
+    /// destroys the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_bytes())?;
+        fs::rename(&temporary, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::filter::{FilterKind, PixelFilter};
+
+    #[test]
+    fn test_round_trip_is_exact() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        for (i, sample) in [0.1, 2.5, 0.33333].iter().enumerate() {
+            framebuffer.pixel_mut(i as u32, 1).add(&Color::build(*sample, 1.0 / 3.0, 7.0));
+            framebuffer.pixel_mut(i as u32, 1).add(&Color::build(0.7, *sample, 0.0));
+            let filter = PixelFilter::new(FilterKind::Mitchell);
+            framebuffer.filtered.splat(i as f32 + 0.3, 1.7, &Color::build(*sample, 0.1, 0.2), &filter);
+        }
+        let checkpoint = Checkpoint { fingerprint: 0xdeadbeef, seed: 42, pass: 3, framebuffer };
+
+        let loaded = Checkpoint::from_bytes(&checkpoint.to_bytes()).unwrap();
+        assert_eq!(loaded.fingerprint, 0xdeadbeef);
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.pass, 3);
+        assert_eq!(loaded.framebuffer.pixels(), checkpoint.framebuffer.pixels());
+        assert_eq!(loaded.framebuffer.filtered.pixels(), checkpoint.framebuffer.filtered.pixels());
+    }
+
+    #[test]
+    fn test_rejects_corrupt_data() {
+        let checkpoint = Checkpoint { fingerprint: 1, seed: 2, pass: 0, framebuffer: Framebuffer::new(2, 2) };
+        let bytes = checkpoint.to_bytes();
+        assert!(Checkpoint::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Checkpoint::from_bytes(b"PNG and other things").is_err());
+    }
+}
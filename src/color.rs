@@ -30,3 +30,14 @@ pub fn write_color(x: u32, y: u32, img_buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8
 
     img_buffer.put_pixel(x, y, Rgba([rbyte, gbyte, bbyte, 255]));
 }
+
+/// Gamma corrected 8 bit image of linear `colors` stored row by row.
+pub fn colors_to_image(width: u32, height: u32, colors: &[Color]) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mut img = ImageBuffer::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            write_color(x, y, &mut img, &colors[(y * width + x) as usize]);
+        }
+    }
+    img
+}
@@ -0,0 +1,144 @@
+use std::rc::Rc;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::{Interval, UNIVERSE_INTERVAL};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use crate::stats::{self, Counter};
+use raytracing::rng::Rng;
+
+/// A volume of constant density, like fog or smoke, filling the inside of a boundary shape.
+/// The boundary must be closed and convex for the entry/exit search to work.
+pub struct ConstantMedium {
+    boundary: Rc<dyn Hittable>,
+    neg_inv_density: f32,
+    /// Phase function used when a ray scatters inside the volume, usually
+    /// `Material::Isotropic` or `Material::HenyeyGreenstein`.
+    pub phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Rc<dyn Hittable>, density: f32, phase_function: Material) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+
+    /// Where the ray enters and leaves the volume, clipped to `ray_t`.
+    fn segment(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<(f32, f32)> {
+        // Find where the ray enters and leaves the boundary, regardless of the ray interval
+        let mut rec1 = HitRecord::new();
+        let mut rec2 = HitRecord::new();
+
+        if !self.boundary.hit(r, &UNIVERSE_INTERVAL, &mut rec1, rng) {
+            return None;
+        }
+        if !self.boundary.hit(r, &Interval::with_bounds(rec1.t + 0.0001, f32::INFINITY), &mut rec2, rng) {
+            return None;
+        }
+
+        // Clip the segment inside the volume to the ray interval
+        let t_enter = rec1.t.max(ray_t.min).max(0.0);
+        let t_exit = rec2.t.min(ray_t.max);
+        (t_enter < t_exit).then_some((t_enter, t_exit))
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord, rng: &mut Rng) -> bool {
+        stats::count(Counter::MediumTests);
+        let (t_enter, t_exit) = match self.segment(r, ray_t, rng) {
+            Some(segment) => segment,
+            None => return false,
+        };
+
+        // Sample a free flight distance from the exponential distribution of the density
+        let ray_length = r.direction().length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * (1.0 - rng.next_f32()).ln();
+
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = t_enter + hit_distance / ray_length;
+        rec.point = r.at(rec.t);
+        // Normal and face are meaningless inside a volume
+        rec.normal = Vec3::build(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.material = self.phase_function;
+
+        true
+    }
+
+    /// Beer-Lambert law, exact for a constant density.
+    fn transmittance(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> f32 {
+        stats::count(Counter::MediumTests);
+        match self.segment(r, ray_t, rng) {
+            Some((t_enter, t_exit)) => ((t_exit - t_enter) * r.direction().length() / self.neg_inv_density).exp(),
+            None => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Isotropic;
+    use crate::color::Color;
+    use crate::quad::make_box;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn test_free_flights_follow_beer_lambert() {
+        // Slab one unit thick along z, wide enough that no ray leaves through the sides
+        let slab = make_box(&Point3::build(-10.0, -10.0, 0.0), &Point3::build(10.0, 10.0, 1.0), Material::default());
+        let density = 1.5;
+        let medium = ConstantMedium::new(Rc::new(slab), density, Material::Isotropic(Isotropic::new(Color::new())));
+
+        let n = 20000;
+        let mut rng = Rng::new(3);
+        let mut passed = 0;
+        let mut mean_depth = 0.0;
+        for i in 0..n {
+            // Rays with different lengths, the distance is measured in world units
+            let r = Ray::new(&Point3::build(0.0, 0.0, -1.0), &Vec3::build(0.0, 0.0, 1.0 + (i % 3) as f32));
+            let mut rec = HitRecord::new();
+            if medium.hit(&r, &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut rng) {
+                assert!((0.0..=1.0).contains(&rec.point.z));
+                mean_depth += rec.point.z;
+            } else {
+                passed += 1;
+            }
+        }
+        let transmitted = passed as f32 / n as f32;
+        assert!((transmitted - (-density).exp()).abs() < 0.01, "{}", transmitted);
+        let r = Ray::new(&Point3::build(0.0, 0.0, -1.0), &Vec3::build(0.0, 0.0, 2.0));
+        assert!((medium.transmittance(&r, &Interval::with_bounds(0.001, f32::INFINITY), &mut rng) - (-density).exp()).abs() < 1e-6);
+        // Shadow rays ending halfway through the slab only see half of it
+        assert!((medium.transmittance(&r, &Interval::with_bounds(0.001, 0.75), &mut rng) - (-density / 2.0).exp()).abs() < 1e-6);
+
+        // Mean depth of the collisions of an exponential truncated to the slab
+        let expected = 1.0 / density - (-density).exp() / (1.0 - (-density).exp());
+        assert!((mean_depth / (n - passed) as f32 - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_rays_starting_inside_the_boundary() {
+        // Only the far root of the sphere lies ahead, the volume ends there and not at the
+        // root behind the ray
+        let sphere = Sphere::new(&Point3::new(), 2.0, Material::default());
+        let medium = ConstantMedium::new(Rc::new(sphere), 0.5, Material::Isotropic(Isotropic::new(Color::new())));
+        let r = Ray::new(&Point3::new(), &Vec3::build(1.0, 0.0, 0.0));
+        let mut rng = Rng::new(3);
+        let n = 20000;
+        let passed = (0..n)
+            .filter(|_| !medium.hit(&r, &Interval::with_bounds(0.001, f32::INFINITY), &mut HitRecord::new(), &mut rng))
+            .count();
+        assert!((passed as f32 / n as f32 - (-1.0f32).exp()).abs() < 0.01);
+    }
+}
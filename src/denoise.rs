@@ -0,0 +1,202 @@
+use crate::aov::{Aov, AovBuffer};
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::vec3::Vec3;
+
+/// Taps of the B3 spline, the smoothing kernel of the à-trous wavelet transform.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo below this is treated as this when dividing it out, so black surfaces keep their
+/// (black) color instead of blowing up.
+const MIN_ALBEDO: f32 = 0.01;
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Every iteration blurs the image
+/// with a 5x5 kernel whose taps lie twice as far apart as in the previous one, weighting each
+/// tap down the more its color, shading normal or albedo differ from the center pixel's.
+///
+/// The filter works on the image divided by the albedo, so textures survive and only the
+/// lighting is smoothed.
+#[derive(Copy, Clone, Debug)]
+pub struct Denoiser {
+    /// Number of wavelet levels, the filter reaches `2^(iterations + 1)` pixels far.
+    pub iterations: u32,
+    /// Tolerated color difference in the first iteration, halved with every further one.
+    /// Higher values remove more noise but also more detail.
+    pub color_sigma: f32,
+    /// Tolerated distance between unit normals.
+    pub normal_sigma: f32,
+    /// Tolerated albedo difference.
+    pub albedo_sigma: f32,
+}
+
+impl Denoiser {
+    pub fn new() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 0.5,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        }
+    }
+
+    /// Denoiser with `color_sigma` scaled by `strength`, 1 being the default.
+    pub fn with_strength(strength: f32) -> Self {
+        let mut denoiser = Self::new();
+        denoiser.color_sigma *= strength.max(0.0);
+        denoiser
+    }
+
+    /// Denoised pixel means of `framebuffer`, row by row, guided by the albedo and shading
+    /// normals in `aovs`.
+    pub fn denoise(&self, framebuffer: &Framebuffer, aovs: &AovBuffer) -> Vec<Color> {
+        let (width, height) = (framebuffer.width, framebuffer.height);
+        let to_vec = |v: [f32; 3]| Vec3::build(v[0], v[1], v[2]);
+        let mut albedo = Vec::with_capacity((width * height) as usize);
+        let mut normal = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                albedo.push(to_vec(aovs.pixel(x, y).value(Aov::Albedo)));
+                normal.push(to_vec(aovs.pixel(x, y).value(Aov::ShadingNormal)));
+            }
+        }
+
+        let demodulate = |a: &Color| Color::build(a.x.max(MIN_ALBEDO), a.y.max(MIN_ALBEDO), a.z.max(MIN_ALBEDO));
+        let mut irradiance = Vec::with_capacity(albedo.len());
+        for y in 0..height {
+            for x in 0..width {
+                let (color, a) = (framebuffer.color(x, y), demodulate(&albedo[(y * width + x) as usize]));
+                irradiance.push(Color::build(color.x / a.x, color.y / a.y, color.z / a.z));
+            }
+        }
+
+        let mut color_sigma = self.color_sigma;
+        for iteration in 0..self.iterations {
+            irradiance = self.filter_level(&irradiance, &albedo, &normal, width, height, 1 << iteration, color_sigma);
+            color_sigma *= 0.5;
+        }
+
+        irradiance
+            .iter()
+            .zip(&albedo)
+            .map(|(e, a)| *e * demodulate(a))
+            .collect()
+    }
+
+    /// One iteration of the filter with taps `step` pixels apart.
+    #[allow(clippy::too_many_arguments)]
+    fn filter_level(&self, color: &[Color], albedo: &[Color], normal: &[Vec3], width: u32, height: u32, step: i64, color_sigma: f32) -> Vec<Color> {
+        let inv_color = 1.0 / (color_sigma * color_sigma).max(1e-12);
+        let inv_normal = 1.0 / (self.normal_sigma * self.normal_sigma).max(1e-12);
+        let inv_albedo = 1.0 / (self.albedo_sigma * self.albedo_sigma).max(1e-12);
+        // Compare colors after Reinhard tone mapping, so fireflies don't stand out
+        let tone_map = |c: &Color| Color::build(c.x / (1.0 + c.x), c.y / (1.0 + c.y), c.z / (1.0 + c.z));
+
+        let mut result = Vec::with_capacity(color.len());
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let p = (y * width as i64 + x) as usize;
+                let center = tone_map(&color[p]);
+                let mut sum = Color::new();
+                let mut weight_sum = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let qy = y + (j as i64 - 2) * step;
+                    if qy < 0 || qy >= height as i64 {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i64 - 2) * step;
+                        if qx < 0 || qx >= width as i64 {
+                            continue;
+                        }
+                        let q = (qy * width as i64 + qx) as usize;
+                        let distance = (tone_map(&color[q]) - center).length_squared() * inv_color
+                            + (normal[q] - normal[p]).length_squared() * inv_normal
+                            + (albedo[q] - albedo[p]).length_squared() * inv_albedo;
+                        let weight = kx * ky * (-distance).exp();
+                        sum += color[q] * weight;
+                        weight_sum += weight;
+                    }
+                }
+                // The center tap always has a positive weight
+                result.push(sum / weight_sum);
+            }
+        }
+        result
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::AovSample;
+    use raytracing::rng::Rng;
+
+    /// Noisy image of two flat halves with different albedo, lit by the same light.
+    fn noisy_halves(size: u32) -> (Framebuffer, AovBuffer) {
+        let mut framebuffer = Framebuffer::new(size, size);
+        let mut aovs = AovBuffer::new(size, size);
+        let mut rng = Rng::new(3);
+        for y in 0..size {
+            for x in 0..size {
+                let albedo = if x < size / 2 { 0.2 } else { 0.8 };
+                let mut sample = AovSample::new();
+                sample.hit = true;
+                sample.albedo = Color::build(albedo, albedo, albedo);
+                sample.shading_normal = Vec3::build(0.0, 0.0, 1.0);
+                aovs.pixel_mut(x, y).add(&sample);
+
+                let noisy = albedo * 2.0 * rng.next_f32();
+                framebuffer.pixel_mut(x, y).add(&Color::build(noisy, noisy, noisy));
+            }
+        }
+        (framebuffer, aovs)
+    }
+
+    fn half_error(size: u32, colors: &[Color], left: bool, expected: f32) -> f32 {
+        let mut error = 0.0;
+        let mut count = 0;
+        for y in 0..size {
+            for x in 0..size {
+                if (x < size / 2) == left {
+                    let c = colors[(y * size + x) as usize].x;
+                    error += (c - expected) * (c - expected);
+                    count += 1;
+                }
+            }
+        }
+        error / count as f32
+    }
+
+    #[test]
+    fn test_removes_noise_and_keeps_albedo_edges() {
+        let size = 32;
+        let (framebuffer, aovs) = noisy_halves(size);
+        let noisy: Vec<Color> = framebuffer.pixels().iter().map(|p| p.mean).collect();
+        let denoised = Denoiser::new().denoise(&framebuffer, &aovs);
+
+        for (left, expected) in [(true, 0.2), (false, 0.8)] {
+            let before = half_error(size, &noisy, left, expected);
+            let after = half_error(size, &denoised, left, expected);
+            assert!(after < before * 0.3, "error {} -> {}", before, after);
+        }
+        // Nothing bleeds over the edge
+        let column_mean = |x: u32| (0..size).map(|y| denoised[(y * size + x) as usize].x).sum::<f32>() / size as f32;
+        assert!((column_mean(size / 2 - 1) - 0.2).abs() < 0.05);
+        assert!((column_mean(size / 2) - 0.8).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_zero_strength_keeps_image() {
+        let (framebuffer, aovs) = noisy_halves(8);
+        let denoised = Denoiser::with_strength(0.0).denoise(&framebuffer, &aovs);
+        for (pixel, color) in framebuffer.pixels().iter().zip(&denoised) {
+            assert!((pixel.mean - *color).length() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+/// Piecewise constant 1D distribution over [0, 1) built from non-negative function values.
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    func_int: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f32]) -> Self {
+        let n = func.len().max(1);
+        let func: Vec<f32> = if func.is_empty() { vec![0.0] } else { func.iter().map(|f| f.max(0.0)).collect() };
+
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1] / n as f32;
+        }
+        let func_int = cdf[n];
+
+        if func_int == 0.0 {
+            // Nothing to importance sample, fall back to uniform
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f32 / n as f32;
+            }
+        } else {
+            for c in cdf.iter_mut() {
+                *c /= func_int;
+            }
+        }
+
+        Self { func, cdf, func_int }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Integral of the function over [0, 1).
+    pub fn integral(&self) -> f32 {
+        self.func_int
+    }
+
+    /// Maps a uniform `u` to a sample in [0, 1). Returns the sample, its density and the
+    /// index of the segment it fell in.
+    pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+        // Last cdf entry that is <= u
+        let offset = match self.cdf.partition_point(|&c| c <= u) {
+            0 => 0,
+            i => (i - 1).min(self.count() - 1),
+        };
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let x = ((offset as f32 + du) / self.count() as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf_at(offset), offset)
+    }
+
+    /// Density of the segment `index`.
+    pub fn pdf_at(&self, index: usize) -> f32 {
+        if self.func_int == 0.0 {
+            return 1.0;
+        }
+        self.func[index] / self.func_int
+    }
+
+    /// Density at `x` in [0, 1).
+    pub fn pdf(&self, x: f32) -> f32 {
+        let index = ((x * self.count() as f32) as usize).min(self.count() - 1);
+        self.pdf_at(index)
+    }
+}
+
+/// Piecewise constant 2D distribution over [0, 1)^2, sampled as a marginal distribution over
+/// rows followed by the conditional distribution within the row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `nu * nv` values with u varying fastest.
+    pub fn new(func: &[f32], nu: usize, nv: usize) -> Self {
+        assert_eq!(func.len(), nu * nv, "distribution data does not match its size");
+        let conditional: Vec<Distribution1D> = func.chunks(nu).map(Distribution1D::new).collect();
+        let row_integrals: Vec<f32> = conditional.iter().map(|d| d.integral()).collect();
+        let marginal = Distribution1D::new(&row_integrals);
+        Self { conditional, marginal }
+    }
+
+    /// Maps two uniform numbers to a point in [0, 1)^2 and its density.
+    pub fn sample_continuous(&self, u0: f32, u1: f32) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let row = ((v * self.marginal.count() as f32) as usize).min(self.marginal.count() - 1);
+        self.conditional[row].pdf(u) * self.marginal.pdf_at(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1d_follows_function() {
+        let dist = Distribution1D::new(&[0.0, 1.0, 3.0, 0.0]);
+        assert_eq!(dist.integral(), 1.0);
+        assert_eq!(dist.pdf(0.1), 0.0);
+        assert_eq!(dist.pdf(0.3), 1.0);
+        assert_eq!(dist.pdf(0.6), 3.0);
+
+        // The first quarter of the cdf maps into the second segment, the rest into the third
+        let (x, pdf, offset) = dist.sample_continuous(0.125);
+        assert_eq!(offset, 1);
+        assert!((x - 0.375).abs() < 1e-6);
+        assert_eq!(pdf, 1.0);
+        assert_eq!(dist.sample_continuous(0.9).2, 2);
+    }
+
+    #[test]
+    fn test_1d_all_zero_is_uniform() {
+        let dist = Distribution1D::new(&[0.0, 0.0]);
+        let (x, pdf, _) = dist.sample_continuous(0.75);
+        assert!((x - 0.75).abs() < 1e-6);
+        assert_eq!(pdf, 1.0);
+    }
+
+    #[test]
+    fn test_2d_pdf_matches_sample() {
+        let dist = Distribution2D::new(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3, 2);
+        for (u0, u1) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.95)] {
+            let ((u, v), pdf) = dist.sample_continuous(u0, u1);
+            assert!((dist.pdf(u, v) - pdf).abs() < 1e-5);
+        }
+    }
+}
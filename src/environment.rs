@@ -0,0 +1,160 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::codecs::hdr::HdrDecoder;
+use image::ImageResult;
+
+use crate::color::Color;
+use crate::distribution::Distribution2D;
+use crate::pdf::Pdf;
+use crate::vec3::Vec3;
+use crate::sampler::Sampler;
+use raytracing::degrees_to_radians;
+
+/// Image based lighting from an equirectangular (latitude-longitude) HDR image surrounding
+/// the scene. The center of the image is in the -z direction and the top row is straight up.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// Multiplier on the radiance of the map.
+    pub intensity: f32,
+    rotation: f32,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// Loads a Radiance `.hdr` or OpenEXR image, `rotation` turns the map around the y axis
+    /// in degrees.
+    pub fn load<P: AsRef<Path>>(path: P, intensity: f32, rotation: f32) -> ImageResult<Self> {
+        let path = path.as_ref();
+        let is_hdr = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+
+        if is_hdr {
+            // Going through `image::open` would tone map Radiance files down to 8 bits
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+            let meta = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?.iter().map(|p| Color::build(p[0], p[1], p[2])).collect();
+            return Ok(Self::from_pixels(meta.width as usize, meta.height as usize, pixels, intensity, rotation));
+        }
+
+        let img = image::open(path)?.into_rgb32f();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let pixels = img.pixels().map(|p| Color::build(p[0], p[1], p[2])).collect();
+        Ok(Self::from_pixels(width, height, pixels, intensity, rotation))
+    }
+
+    /// Builds a map from row major linear radiance values.
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>, intensity: f32, rotation: f32) -> Self {
+        assert!(width > 0 && height > 0, "environment map must not be empty");
+        assert_eq!(pixels.len(), width * height, "environment map data does not match its size");
+
+        // Importance proportional to luminance, weighted by sin(theta) to account for rows near
+        // the poles covering a smaller solid angle
+        let mut weights = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            for x in 0..width {
+                weights.push(luminance(&pixels[y * width + x]) * sin_theta);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+            intensity,
+            rotation: degrees_to_radians(rotation),
+            distribution: Distribution2D::new(&weights, width, height),
+        }
+    }
+
+    /// Image coordinates in [0, 1)^2 of a world space direction.
+    fn direction_to_uv(&self, direction: &Vec3) -> (f32, f32) {
+        let d = Vec3::normalize(direction);
+        let phi = d.x.atan2(-d.z) - self.rotation;
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = theta / PI;
+        (u, v.min(1.0 - f32::EPSILON))
+    }
+
+    fn uv_to_direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vec3::build(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x] * self.intensity
+    }
+}
+
+/// The map can be sampled as a light. Directions follow the brightness of the image,
+/// so a small sun in the map is found almost every time.
+impl Pdf for EnvironmentMap {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // Convert from density over the image to density over solid angle
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u0, u1) = sampler.get_2d();
+        let ((u, v), _) = self.distribution.sample_continuous(u0, u1);
+        self.uv_to_direction(u, v)
+    }
+}
+
+fn luminance(color: &Color) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    #[test]
+    fn test_direction_round_trip() {
+        let env = EnvironmentMap::from_pixels(4, 2, vec![Color::build(1.0, 1.0, 1.0); 8], 1.0, 30.0);
+        for d in [Vec3::build(0.0, 0.0, -1.0), Vec3::build(0.6, 0.0, 0.8), Vec3::build(-0.3, 0.5, 0.2)] {
+            let (u, v) = env.direction_to_uv(&d);
+            let back = env.uv_to_direction(u, v);
+            assert!((back - Vec3::normalize(&d)).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_bright_texel_is_sampled() {
+        // A dark map with a single bright texel should send nearly all samples towards it
+        let mut pixels = vec![Color::build(0.001, 0.001, 0.001); 64 * 32];
+        pixels[10 * 64 + 40] = Color::build(1000.0, 1000.0, 1000.0);
+        let env = EnvironmentMap::from_pixels(64, 32, pixels, 1.0, 0.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        let hits = (0..1000)
+            .filter(|_| env.radiance(&env.generate(&mut sampler)).x > 1.0)
+            .count();
+        assert!(hits > 950);
+    }
+
+    #[test]
+    fn test_constant_map_pdf_is_uniform() {
+        let env = EnvironmentMap::from_pixels(64, 32, vec![Color::build(1.0, 1.0, 1.0); 64 * 32], 1.0, 0.0);
+        let pdf = env.value(&Vec3::build(1.0, 0.2, 0.0));
+        // Only approximately uniform since sin(theta) is constant per row
+        assert!((pdf - 1.0 / (4.0 * PI)).abs() < 0.01);
+    }
+}
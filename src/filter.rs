@@ -0,0 +1,131 @@
+use std::f32::consts::PI;
+
+/// Shape of a pixel reconstruction filter.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FilterKind {
+    /// Every sample counts fully for the pixels it is close enough to, with radius 0.5 this
+    /// is the plain average of the samples inside each pixel.
+    Box,
+    /// Weight falling off linearly to zero at the radius.
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius, shifted down to reach zero
+    /// at the radius.
+    Gaussian,
+    /// Mitchell–Netravali cubic with B = C = 1/3, slightly sharpening thanks to its negative lobes.
+    Mitchell,
+    /// Windowed sinc with as many lobes as the radius, the sharpest of the filters but prone
+    /// to ringing at hard edges.
+    Lanczos,
+}
+
+/// Weights with which a sample contributes to the pixels around it. The filter is separable,
+/// the weight is the product of the 1D filter applied to the horizontal and vertical offset
+/// between the sample and the pixel center.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PixelFilter {
+    pub kind: FilterKind,
+    /// Distance from the pixel center in pixels beyond which samples have no weight, at most
+    /// `MAX_RADIUS`.
+    pub radius: f32,
+}
+
+impl PixelFilter {
+    pub const MAX_RADIUS: f32 = 4.0;
+
+    /// The filter with its usual radius.
+    pub fn new(kind: FilterKind) -> Self {
+        let radius = match kind {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell | FilterKind::Lanczos => 2.0,
+        };
+        Self { kind, radius }
+    }
+
+    pub fn with_radius(kind: FilterKind, radius: f32) -> Self {
+        Self { kind, radius: radius.clamp(0.5, Self::MAX_RADIUS) }
+    }
+
+    /// Weight of a sample `dx`, `dy` pixels away from a pixel center.
+    #[cfg(test)]
+    pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    /// Weight of a sample `x` pixels away from a pixel center along one axis.
+    pub fn evaluate_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x > self.radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / self.radius,
+            FilterKind::Gaussian => {
+                let sigma = self.radius / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(self.radius)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / self.radius),
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+}
+
+impl Default for PixelFilter {
+    fn default() -> Self {
+        Self::new(FilterKind::Box)
+    }
+}
+
+/// Mitchell–Netravali cubic with B = C = 1/3 for `x` in [0, 2].
+fn mitchell(x: f32) -> f32 {
+    let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+    let result = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+    } else {
+        (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+    };
+    result / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos];
+
+    #[test]
+    fn test_filters_peak_in_the_center_and_vanish_outside() {
+        for kind in KINDS {
+            let filter = PixelFilter::new(kind);
+            let center = filter.evaluate(0.0, 0.0);
+            assert!(center > 0.0, "{:?}", kind);
+            for i in 1..20 {
+                let x = i as f32 * filter.radius / 20.0;
+                assert!(filter.evaluate(x, 0.0) <= center, "{:?} at {}", kind, x);
+                assert_eq!(filter.evaluate(x, 0.0), filter.evaluate(-x, 0.0));
+            }
+            assert_eq!(filter.evaluate(filter.radius + 0.01, 0.0), 0.0);
+            assert_eq!(filter.evaluate(0.0, -filter.radius - 0.01), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_mitchell_and_lanczos_have_negative_lobes() {
+        assert!(PixelFilter::new(FilterKind::Mitchell).evaluate(1.5, 0.0) < 0.0);
+        assert!(PixelFilter::new(FilterKind::Lanczos).evaluate(1.5, 0.0) < 0.0);
+        // Continuous where the pieces meet and zero at the radius
+        assert!((mitchell(1.0 - 1e-4) - mitchell(1.0 + 1e-4)).abs() < 1e-3);
+        assert!(mitchell(2.0).abs() < 1e-6);
+        assert!(PixelFilter::new(FilterKind::Gaussian).evaluate(1.5, 0.0).abs() < 1e-6);
+    }
+}
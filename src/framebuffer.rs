@@ -0,0 +1,393 @@
+use image::{ImageBuffer, Rgba};
+
+use crate::aov::{AovBuffer, AovPixel};
+use crate::color::{write_color, Color};
+use crate::filter::PixelFilter;
+
+/// Running mean and variance of the samples of one pixel, updated with Welford's algorithm so
+/// no samples have to be stored.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PixelStats {
+    pub count: u32,
+    pub mean: Color,
+    mean_luminance: f32,
+    /// Sum of squared differences from the mean luminance.
+    m2: f32,
+}
+
+impl PixelStats {
+    pub const BYTES: usize = 24;
+
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            mean: Color::new(),
+            mean_luminance: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, sample: &Color) {
+        self.count += 1;
+        let n = self.count as f32;
+        self.mean += (*sample - self.mean) / n;
+
+        let luminance = luminance(sample);
+        let delta = luminance - self.mean_luminance;
+        self.mean_luminance += delta / n;
+        self.m2 += delta * (luminance - self.mean_luminance);
+    }
+
+    /// Unbiased sample variance of the luminance.
+    pub fn variance(&self) -> f32 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f32
+    }
+
+    /// Estimated standard error of the pixel's mean luminance relative to the mean itself.
+    /// Dark pixels are measured against a floor, as their absolute error is invisible anyway.
+    pub fn relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let standard_error = (self.variance() / self.count as f32).sqrt();
+        standard_error / self.mean_luminance.max(0.01)
+    }
+
+    /// Exact binary copy of the statistics: the count followed by the mean, mean luminance and
+    /// squared differences as little endian `f32`s.
+    pub fn to_le_bytes(self) -> [u8; PixelStats::BYTES] {
+        let mut bytes = [0; PixelStats::BYTES];
+        bytes[0..4].copy_from_slice(&self.count.to_le_bytes());
+        let values = [self.mean.x, self.mean.y, self.mean.z, self.mean_luminance, self.m2];
+        for (i, v) in values.iter().enumerate() {
+            bytes[4 + i * 4..8 + i * 4].copy_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Inverse of `to_le_bytes`, `bytes` has to hold at least `PixelStats::BYTES` bytes.
+    pub fn from_le_bytes(bytes: &[u8]) -> Self {
+        let read = |i: usize| f32::from_le_bytes(bytes[4 + i * 4..8 + i * 4].try_into().unwrap());
+        Self {
+            count: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            mean: Color::build(read(0), read(1), read(2)),
+            mean_luminance: read(3),
+            m2: read(4),
+        }
+    }
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Filter weighted sum of the samples splatted onto one pixel.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FilteredPixel {
+    pub sum: Color,
+    pub weight: f32,
+}
+
+impl FilteredPixel {
+    pub const BYTES: usize = 16;
+
+    pub fn new() -> Self {
+        Self { sum: Color::new(), weight: 0.0 }
+    }
+
+    /// Little endian `f32`s of the sum followed by the weight.
+    pub fn to_le_bytes(self) -> [u8; FilteredPixel::BYTES] {
+        let mut bytes = [0; FilteredPixel::BYTES];
+        for (i, v) in [self.sum.x, self.sum.y, self.sum.z, self.weight].iter().enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Inverse of `to_le_bytes`, `bytes` has to hold at least `FilteredPixel::BYTES` bytes.
+    pub fn from_le_bytes(bytes: &[u8]) -> Self {
+        let read = |i: usize| f32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        Self { sum: Color::build(read(0), read(1), read(2)), weight: read(3) }
+    }
+}
+
+impl Default for FilteredPixel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reconstructed image: every sample is splatted onto the pixels within the radius of the
+/// filter, weighted by the filter.
+#[derive(Clone)]
+pub struct FilteredImage {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<FilteredPixel>,
+}
+
+impl FilteredImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, pixels: vec![FilteredPixel::new(); (width * height) as usize] }
+    }
+
+    pub fn pixels(&self) -> &[FilteredPixel] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &FilteredPixel {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Adds a sample at the film position (`film_x`, `film_y`), in pixels from the top left
+    /// corner of the image, to every pixel whose center is within the filter's radius.
+    pub fn splat(&mut self, film_x: f32, film_y: f32, color: &Color, filter: &PixelFilter) {
+        // A pixel's center is at half integer coordinates. Of two pixels exactly at the
+        // radius, only the one in the positive direction gets the sample, so the box filter
+        // with radius 0.5 gives every sample to exactly one pixel.
+        let range = |center: f32, size: u32| {
+            let first = (center - 0.5 - filter.radius).floor().max(0.0) as u32;
+            let last = ((center - 0.5 + filter.radius).ceil().max(0.0) as u32).min(size.saturating_sub(1));
+            first..=last
+        };
+        let inside = |d: f32| -filter.radius < d && d <= filter.radius;
+        let weight = |d: f32| if inside(d) { filter.evaluate_1d(d) } else { 0.0 };
+
+        // The filter is separable, so the horizontal weights are the same in every row
+        let columns = range(film_x, self.width);
+        let first_column = *columns.start();
+        let mut column_weights = [0.0; 2 * PixelFilter::MAX_RADIUS as usize + 2];
+        for x in columns.clone() {
+            column_weights[(x - first_column) as usize] = weight(x as f32 + 0.5 - film_x);
+        }
+        for y in range(film_y, self.height) {
+            let row_weight = weight(y as f32 + 0.5 - film_y);
+            if row_weight == 0.0 {
+                continue;
+            }
+            for x in columns.clone() {
+                let weight = row_weight * column_weights[(x - first_column) as usize];
+                if weight == 0.0 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[(y * self.width + x) as usize];
+                pixel.sum += *color * weight;
+                pixel.weight += weight;
+            }
+        }
+    }
+}
+
+/// Floating point image accumulating statistics for every pixel.
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<PixelStats>,
+    /// The image output, as reconstructed by the camera's pixel filter.
+    pub filtered: FilteredImage,
+    /// Auxiliary outputs, accumulated alongside the pixels when requested.
+    pub aovs: Option<AovBuffer>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![PixelStats::new(); (width * height) as usize],
+            filtered: FilteredImage::new(width, height),
+            aovs: None,
+        }
+    }
+
+    /// Framebuffer with existing statistics and filtered pixels, stored row by row.
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<PixelStats>, filtered: Vec<FilteredPixel>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize, "pixel count doesn't match the size");
+        assert_eq!(filtered.len(), pixels.len(), "filtered pixel count doesn't match the size");
+        Self { width, height, pixels, filtered: FilteredImage { width, height, pixels: filtered }, aovs: None }
+    }
+
+    pub fn pixels(&self) -> &[PixelStats] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &PixelStats {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    #[cfg(test)]
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut PixelStats {
+        &mut self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// The statistics and, if there are any, the AOVs of a pixel to add samples to, together
+    /// with the image to splat the samples onto.
+    pub fn sample_mut(&mut self, x: u32, y: u32) -> (&mut PixelStats, Option<&mut AovPixel>, &mut FilteredImage) {
+        let index = (y * self.width + x) as usize;
+        let aov = self.aovs.as_mut().map(|aovs| aovs.pixel_mut(x, y));
+        (&mut self.pixels[index], aov, &mut self.filtered)
+    }
+
+    /// Reconstructed color of a pixel. Filters with negative lobes can leave a pixel without
+    /// positive weight; those fall back to the mean of their own samples.
+    pub fn color(&self, x: u32, y: u32) -> Color {
+        let filtered = self.filtered.pixel(x, y);
+        if filtered.weight <= 1e-6 {
+            return self.pixel(x, y).mean;
+        }
+        let color = filtered.sum / filtered.weight;
+        Color::build(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0))
+    }
+
+    /// Largest relative error of the pixel and its eight neighbours. Looking at neighbours
+    /// keeps a pixel whose first few samples happened to agree from being taken as converged.
+    pub fn neighbourhood_error(&self, x: u32, y: u32) -> f32 {
+        let mut error: f32 = 0.0;
+        for ny in y.saturating_sub(1)..(y + 2).min(self.height) {
+            for nx in x.saturating_sub(1)..(x + 2).min(self.width) {
+                error = error.max(self.pixel(nx, ny).relative_error());
+            }
+        }
+        error
+    }
+
+    /// Average relative error over all pixels, a single number for how noisy the image is.
+    pub fn mean_relative_error(&self) -> f32 {
+        let errors: Vec<f32> = self.pixels.iter().map(|p| p.relative_error()).filter(|e| e.is_finite()).collect();
+        if errors.is_empty() {
+            return f32::INFINITY;
+        }
+        errors.iter().sum::<f32>() / errors.len() as f32
+    }
+
+    /// Highest sample count of any pixel.
+    pub fn max_samples(&self) -> u32 {
+        self.pixels.iter().map(|p| p.count).max().unwrap_or(0)
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| p.count as u64).sum()
+    }
+
+    /// Gamma corrected 8 bit image of the reconstructed colors.
+    pub fn to_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut img = ImageBuffer::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                write_color(x, y, &mut img, &self.color(x, y));
+            }
+        }
+        img
+    }
+
+    /// Visualizes how many samples each pixel received, from blue for the fewest over green
+    /// to red for the most.
+    pub fn sample_heatmap(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let min = self.pixels.iter().map(|p| p.count).min().unwrap_or(0);
+        let max = self.pixels.iter().map(|p| p.count).max().unwrap_or(0);
+        let range = (max - min).max(1) as f32;
+
+        let mut img = ImageBuffer::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let t = (self.pixel(x, y).count - min) as f32 / range;
+                let (r, g, b) = if t < 0.5 {
+                    (0.0, 2.0 * t, 1.0 - 2.0 * t)
+                } else {
+                    (2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+                };
+                img.put_pixel(x, y, Rgba([(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8, 255]));
+            }
+        }
+        img
+    }
+}
+
+pub fn luminance(color: &Color) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    #[test]
+    fn test_welford_matches_two_pass() {
+        let samples = [0.2, 1.5, 0.7, 3.0, 0.1, 0.9];
+        let mut stats = PixelStats::new();
+        for s in samples {
+            stats.add(&Color::build(s, s, s));
+        }
+
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let variance = samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f32>() / (n - 1.0);
+        assert!((stats.mean.x - mean).abs() < 1e-5);
+        assert!((stats.variance() - variance).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_constant_pixel_has_no_error() {
+        let mut stats = PixelStats::new();
+        assert_eq!(stats.relative_error(), f32::INFINITY);
+        for _ in 0..4 {
+            stats.add(&Color::build(0.5, 0.5, 0.5));
+        }
+        assert!(stats.relative_error() < 1e-6);
+    }
+
+    #[test]
+    fn test_neighbourhood_error_includes_neighbours() {
+        let mut fb = Framebuffer::new(3, 3);
+        for y in 0..3 {
+            for x in 0..3 {
+                for _ in 0..4 {
+                    fb.pixel_mut(x, y).add(&Color::build(0.5, 0.5, 0.5));
+                }
+            }
+        }
+        fb.pixel_mut(2, 2).add(&Color::build(5.0, 5.0, 5.0));
+        assert!(fb.neighbourhood_error(1, 1) > 0.1);
+        assert!(fb.neighbourhood_error(0, 0) < 1e-6);
+    }
+
+    #[test]
+    fn test_splat_weights_neighbours() {
+        let mut image = FilteredImage::new(3, 3);
+        let white = Color::build(1.0, 1.0, 1.0);
+        image.splat(1.5, 1.5, &white, &PixelFilter::new(FilterKind::Box));
+        image.splat(1.0, 1.5, &white, &PixelFilter::new(FilterKind::Box));
+        let weights: Vec<f32> = image.pixels().iter().map(|p| p.weight).collect();
+        assert_eq!(weights, vec![0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 0.0]);
+
+        let mut image = FilteredImage::new(3, 3);
+        image.splat(1.0, 1.5, &white, &PixelFilter::new(FilterKind::Tent));
+        assert_eq!(image.pixel(0, 1).weight, 0.5);
+        assert_eq!(image.pixel(1, 1).weight, 0.5);
+        assert_eq!(image.pixel(2, 1).weight, 0.0);
+        assert_eq!(image.pixel(1, 0).weight, 0.0);
+
+        // Samples near the border only reach the pixels inside the image
+        image.splat(0.1, 0.1, &white, &PixelFilter::new(FilterKind::Gaussian));
+        assert!(image.pixel(0, 0).weight > 0.0);
+    }
+
+    #[test]
+    fn test_heatmap_extremes() {
+        let mut fb = Framebuffer::new(2, 1);
+        fb.pixel_mut(0, 0).add(&Color::new());
+        for _ in 0..10 {
+            fb.pixel_mut(1, 0).add(&Color::new());
+        }
+        let heatmap = fb.sample_heatmap();
+        assert_eq!(heatmap.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(heatmap.get_pixel(1, 0).0, [255, 0, 0, 255]);
+    }
+}
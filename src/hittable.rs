@@ -1,6 +1,8 @@
 use crate::{ray::Ray, vec3::*};
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::sampler::Sampler;
+use raytracing::rng::Rng;
 
 #[derive(Copy, Clone)]
 pub struct HitRecord {
@@ -9,6 +11,14 @@ pub struct HitRecord {
     pub material: Material,
     pub t: f32,
     pub front_face: bool,
+    /// Surface coordinates of the hit point, in [0, 1] for the primitives that define them.
+    pub u: f32,
+    pub v: f32,
+    /// 1 + index of the hit object in the outermost `HittableList`, 0 if nothing was hit.
+    pub object_id: u32,
+    /// How far the hit point moved since the previous frame of an animation, zero for objects
+    /// that don't move.
+    pub motion: Vec3,
 }
 
 impl HitRecord {
@@ -19,6 +29,10 @@ impl HitRecord {
             material: Material::default(),
             t: 0.0,
             front_face: false,
+            u: 0.0,
+            v: 0.0,
+            object_id: 0,
+            motion: Vec3::new(),
         }
     }
 
@@ -29,5 +43,25 @@ impl HitRecord {
 }
 
 pub trait Hittable {
-    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool;
+    /// Closest hit of `r` within `ray_t`. Objects that sample where they are hit, like media,
+    /// draw their random numbers from `rng`.
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord, rng: &mut Rng) -> bool;
+
+    /// Fraction of the light travelling along `r` within `ray_t` that gets through the object,
+    /// used for shadow rays. Surfaces block everything they are hit with, media return an
+    /// unbiased estimate of their transmittance.
+    fn transmittance(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> f32 {
+        if self.hit(r, ray_t, &mut HitRecord::new(), rng) { 0.0 } else { 1.0 }
+    }
+
+    /// Solid angle probability density of `random` producing `direction` from `origin`.
+    /// Objects that can't be sampled as lights return 0.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f32 {
+        0.0
+    }
+
+    /// Returns a random direction from `origin` towards a point on the object.
+    fn random(&self, _origin: &Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::build(1.0, 0.0, 0.0)
+    }
 }
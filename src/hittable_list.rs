@@ -1,6 +1,9 @@
 use std::rc::Rc;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::vec3::{Point3, Vec3};
+use crate::sampler::Sampler;
+use raytracing::rng::Rng;
 
 pub struct HittableList {
     pub objects: Vec<Rc<dyn Hittable>>,
@@ -23,19 +26,80 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &crate::ray::Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &crate::ray::Ray, ray_t: &Interval, rec: &mut HitRecord, rng: &mut Rng) -> bool {
         let mut temp_rec = HitRecord::new();
         let mut hit_anything: bool = false;
         let mut closest_so_far = ray_t.max;
 
-        for object in &self.objects {
-            if object.hit(&r, &Interval::with_bounds(ray_t.min, closest_so_far), &mut temp_rec) {
+        for (index, object) in self.objects.iter().enumerate() {
+            // Only animated objects set it
+            temp_rec.motion = Vec3::new();
+            if object.hit(&r, &Interval::with_bounds(ray_t.min, closest_so_far), &mut temp_rec, rng) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
+                temp_rec.object_id = index as u32 + 1;
                 *rec = temp_rec;
             }
         }
 
         hit_anything
     }
+
+    fn transmittance(&self, r: &crate::ray::Ray, ray_t: &Interval, rng: &mut Rng) -> f32 {
+        let mut transmittance = 1.0;
+        for object in &self.objects {
+            transmittance *= object.transmittance(r, ray_t, rng);
+            if transmittance <= 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
+
+    /// Mixture of the objects' densities, each picked with equal probability by `random`.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f32;
+        self.objects.iter().map(|object| weight * object.pdf_value(origin, direction)).sum()
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::build(1.0, 0.0, 0.0);
+        }
+        let index = ((sampler.get_1d() * self.objects.len() as f32) as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::sampler::SobolSampler;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_light_list_pdf_is_the_mixture() {
+        let near = Rc::new(Sphere::new(&Point3::build(0.0, 0.0, -2.0), 0.5, Material::default()));
+        let far = Rc::new(Sphere::new(&Point3::build(3.0, 0.0, 0.0), 0.5, Material::default()));
+        let mut lights = HittableList::new();
+        lights.add(near.clone());
+        lights.add(far.clone());
+
+        let mut sampler = SobolSampler::new(0);
+        let mut picked_far = 0;
+        for i in 0..64 {
+            sampler.start_pixel_sample(0, 0, i);
+            let direction = lights.random(&Point3::new(), &mut sampler);
+            let (p_near, p_far) = (near.pdf_value(&Point3::new(), &direction), far.pdf_value(&Point3::new(), &direction));
+            // The two spheres don't overlap as seen from the origin
+            assert!((p_near > 0.0) != (p_far > 0.0));
+            picked_far += (p_far > 0.0) as u32;
+            assert_eq!(lights.pdf_value(&Point3::new(), &direction), 0.5 * (p_near + p_far));
+        }
+        assert_eq!(picked_far, 32);
+    }
 }
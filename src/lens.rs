@@ -0,0 +1,415 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+/// Lens prescriptions and the film are measured in millimetres, scenes in metres.
+const MILLIMETRES: f32 = 0.001;
+
+/// Rings of the film with their own exit pupil bounds.
+const PUPIL_RINGS: usize = 32;
+
+/// One surface of a lens prescription: a spherical interface between two media, or the
+/// aperture stop.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LensElement {
+    /// Radius of the spherical surface, positive when its center lies toward the film. 0 for
+    /// the flat aperture stop.
+    pub curvature_radius: f32,
+    /// Distance along the axis to the next surface, or to the film for the last one.
+    pub thickness: f32,
+    /// Index of refraction of the medium behind the surface, 0 or 1 for air.
+    pub ior: f32,
+    pub aperture_radius: f32,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    fn medium_ior(&self) -> f32 {
+        if self.ior == 0.0 { 1.0 } else { self.ior }
+    }
+}
+
+/// Axis aligned bounds of points on the plane of the rear surface.
+#[derive(Copy, Clone, Debug)]
+struct PupilBounds {
+    min: (f32, f32),
+    max: (f32, f32),
+}
+
+impl PupilBounds {
+    const EMPTY: PupilBounds = PupilBounds { min: (f32::INFINITY, f32::INFINITY), max: (f32::NEG_INFINITY, f32::NEG_INFINITY) };
+
+    fn is_empty(&self) -> bool {
+        self.min.0 > self.max.0
+    }
+
+    fn area(&self) -> f32 {
+        if self.is_empty() { 0.0 } else { (self.max.0 - self.min.0) * (self.max.1 - self.min.1) }
+    }
+
+    fn expand(&mut self, x: f32, y: f32) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+}
+
+/// Camera tracing rays from the film through every surface of a real lens (Kolb et al. 1995),
+/// which gives the lens' own vignetting, distortion and bokeh. In camera space the film lies
+/// in the plane z = 0 and the lens in front of it toward -z.
+///
+/// Rays are only started toward the part of the rear surface they can leave the lens through,
+/// its exit pupil as seen from their ring of the film, precomputed for `PUPIL_RINGS` rings.
+#[derive(Clone, Debug)]
+pub struct RealisticLens {
+    /// Surfaces from the front of the lens to the back.
+    elements: Vec<LensElement>,
+    /// Diagonal of the film in millimetres.
+    film_diagonal: f32,
+    /// Per ring of the film, from the center outward, the bounds of the exit pupil seen from
+    /// a film point on the +x axis.
+    exit_pupils: Vec<PupilBounds>,
+}
+
+impl RealisticLens {
+    /// Loads a lens prescription: one surface per line from the front of the lens to the back,
+    /// as curvature radius, thickness, index of refraction and aperture diameter in millimetres.
+    /// The aperture stop has a radius of 0, and lines starting with `#` are comments.
+    pub fn load<P: AsRef<Path>>(path: P, film_diagonal: f32) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(Self::new(Self::parse(&text)?, film_diagonal))
+    }
+
+    pub fn parse(text: &str) -> io::Result<Vec<LensElement>> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut elements = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| invalid(format!("line {}: expected numbers", number + 1)))?;
+            match values[..] {
+                [curvature_radius, thickness, ior, aperture] if thickness >= 0.0 && ior >= 0.0 && aperture > 0.0 => {
+                    elements.push(LensElement { curvature_radius, thickness, ior, aperture_radius: aperture / 2.0 })
+                }
+                _ => return Err(invalid(format!("line {}: expected radius, thickness, ior and aperture", number + 1))),
+            }
+        }
+        if elements.is_empty() {
+            return Err(invalid("lens has no surfaces".to_string()));
+        }
+        Ok(elements)
+    }
+
+    /// Lens made of `elements` with the film `film_diagonal` millimetres across, focused where
+    /// the thickness of the last element puts it. See `focus` to place the film automatically.
+    pub fn new(elements: Vec<LensElement>, film_diagonal: f32) -> Self {
+        let mut lens = Self { elements, film_diagonal, exit_pupils: Vec::new() };
+        lens.update_exit_pupils();
+        lens
+    }
+
+    #[cfg(test)]
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    /// Focal length in millimetres from a thick lens approximation.
+    #[cfg(test)]
+    pub fn focal_length(&self) -> Option<f32> {
+        let (principal, focal) = self.cardinal_points()?;
+        Some(focal[0] - principal[0])
+    }
+
+    /// Moves the lens so that objects `distance` scene units in front of the film are in
+    /// focus. Returns false, leaving the lens as it was, if it can't focus that close.
+    pub fn focus(&mut self, distance: f32) -> bool {
+        let Some((principal, focal)) = self.cardinal_points() else {
+            return false;
+        };
+        // Thick lens equation for moving the lens by `delta` toward the scene
+        let f = focal[0] - principal[0];
+        let z = -distance / MILLIMETRES;
+        let c = (principal[1] - z - principal[0]) * (principal[1] - z - 4.0 * f - principal[0]);
+        if c < 0.0 {
+            return false;
+        }
+        let delta = 0.5 * (principal[1] - z + principal[0] - c.sqrt());
+        let last = self.elements.last_mut().unwrap();
+        if last.thickness + delta <= 0.0 {
+            return false;
+        }
+        last.thickness += delta;
+        self.update_exit_pupils();
+        true
+    }
+
+    /// Narrows the aperture stop to `diameter` millimetres, at most its size in the prescription.
+    pub fn set_aperture_diameter(&mut self, diameter: f32) {
+        if let Some(stop) = self.elements.iter_mut().find(|element| element.is_stop()) {
+            stop.aperture_radius = (diameter / 2.0).min(stop.aperture_radius);
+            self.update_exit_pupils();
+        }
+    }
+
+    /// Camera space ray through the film position `x`, `y` in pixels of a `width` by `height`
+    /// image, as its origin, direction and the weight of the radiance it brings back. `lens`
+    /// picks the point on the exit pupil. `None` if the ray is blocked inside the lens, or if
+    /// no light reaches the center of the film to normalize the weight with.
+    ///
+    /// The weight accounts for the cos^4 falloff and the size of the exit pupil, normalized
+    /// so the center of the image is about as bright as through a pinhole.
+    pub fn ray(&self, x: f32, y: f32, width: u32, height: u32, lens: (f32, f32)) -> Option<(Point3, Vec3, f32)> {
+        let aspect_ratio = width as f32 / height as f32;
+        let film_height = self.film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let film_width = film_height * aspect_ratio;
+        // The lens turns the image upside down, so the film is flipped to get an upright one
+        let film = Point3::build(
+            -(2.0 * x / width as f32 - 1.0) * film_width / 2.0,
+            -(1.0 - 2.0 * y / height as f32) * film_height / 2.0,
+            0.0,
+        );
+
+        let r = (film.x * film.x + film.y * film.y).sqrt();
+        let ring = ((r / (self.film_diagonal / 2.0) * PUPIL_RINGS as f32) as usize).min(PUPIL_RINGS - 1);
+        let pupil = self.exit_pupils[ring];
+        let axial_area = self.exit_pupils[0].area();
+        if pupil.is_empty() || axial_area <= 0.0 {
+            return None;
+        }
+        let (px, py) = (pupil.min.0 + lens.0 * (pupil.max.0 - pupil.min.0), pupil.min.1 + lens.1 * (pupil.max.1 - pupil.min.1));
+        // The bounds were found for a film point on the x axis
+        let (sin_phi, cos_phi) = if r > 0.0 { (film.y / r, film.x / r) } else { (0.0, 1.0) };
+        let rear = Point3::build(cos_phi * px - sin_phi * py, sin_phi * px + cos_phi * py, self.rear_z());
+
+        let direction = rear - film;
+        let out = self.trace_from_film(&Ray::new(&film, &direction))?;
+        let cos_theta = -Vec3::normalize(&direction).z;
+        let weight = cos_theta.powi(4) * pupil.area() / axial_area;
+        Some((out.origin() * MILLIMETRES, out.direction(), weight))
+    }
+
+    /// Axial position of the rear surface.
+    fn rear_z(&self) -> f32 {
+        -self.elements.last().unwrap().thickness
+    }
+
+    /// Axial position of the front surface.
+    fn front_z(&self) -> f32 {
+        -self.elements.iter().map(|element| element.thickness).sum::<f32>()
+    }
+
+    /// Follows `ray` from the film out of the front of the lens, `None` if it is blocked.
+    fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = *ray;
+        let mut z = 0.0;
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            z -= element.thickness;
+            let outside = if i > 0 { self.elements[i - 1].medium_ior() } else { 1.0 };
+            ray = self.pass(element, z, &ray, element.medium_ior() / outside)?;
+        }
+        Some(ray)
+    }
+
+    /// Follows `ray` from the scene out of the back of the lens, `None` if it is blocked.
+    fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = *ray;
+        let mut z = self.front_z();
+        for (i, element) in self.elements.iter().enumerate() {
+            let outside = if i > 0 { self.elements[i - 1].medium_ior() } else { 1.0 };
+            ray = self.pass(element, z, &ray, outside / element.medium_ior())?;
+            z += element.thickness;
+        }
+        Some(ray)
+    }
+
+    /// Intersects `ray` with the surface `element` whose vertex lies at `z` and refracts it
+    /// with the ratio `eta` of the indices of refraction before and after the surface.
+    fn pass(&self, element: &LensElement, z: f32, ray: &Ray, eta: f32) -> Option<Ray> {
+        let (o, d) = (ray.origin(), ray.direction());
+        let (t, normal) = if element.is_stop() {
+            ((z - o.z) / d.z, None)
+        } else {
+            let radius = element.curvature_radius;
+            let center = Point3::build(0.0, 0.0, z + radius);
+            let oc = o - center;
+            let a = d.length_squared();
+            let half_b = Vec3::dot(&oc, &d);
+            let c = oc.length_squared() - radius * radius;
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let (t0, t1) = ((-half_b - discriminant.sqrt()) / a, (-half_b + discriminant.sqrt()) / a);
+            // The surface is the half of the sphere around the vertex
+            let t = if (d.z > 0.0) ^ (radius < 0.0) { t0.min(t1) } else { t0.max(t1) };
+            let normal = Vec3::normalize(&(o + d * t - center));
+            (t, Some(if Vec3::dot(&normal, &d) > 0.0 { -normal } else { normal }))
+        };
+        if !t.is_finite() || t <= 0.0 {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+            return None;
+        }
+        match normal {
+            Some(normal) => Some(Ray::new(&p, &refract(&d, &normal, eta)?)),
+            None => Some(Ray::new(&p, &d)),
+        }
+    }
+
+    /// Positions of the principal planes and focal points on the axis, on the film side first
+    /// and the scene side second, found by tracing rays parallel to the axis through the lens.
+    fn cardinal_points(&self) -> Option<([f32; 2], [f32; 2])> {
+        let x = 0.01 * self.film_diagonal;
+        let from_scene = Ray::new(&Point3::build(x, 0.0, self.front_z() - 1.0), &Vec3::build(0.0, 0.0, 1.0));
+        let (principal_film, focal_film) = cardinal_point(&from_scene, &self.trace_from_scene(&from_scene)?);
+        let from_film = Ray::new(&Point3::build(x, 0.0, self.rear_z() + 1.0), &Vec3::build(0.0, 0.0, -1.0));
+        let (principal_scene, focal_scene) = cardinal_point(&from_film, &self.trace_from_film(&from_film)?);
+        Some(([principal_film, principal_scene], [focal_film, focal_scene]))
+    }
+
+    fn update_exit_pupils(&mut self) {
+        self.exit_pupils = (0..PUPIL_RINGS)
+            .map(|ring| {
+                let ring_width = self.film_diagonal / 2.0 / PUPIL_RINGS as f32;
+                self.exit_pupil(ring as f32 * ring_width, (ring + 1) as f32 * ring_width)
+            })
+            .collect();
+    }
+
+    /// Bounds of the points on the rear surface's plane through which rays from film points
+    /// between `r0` and `r1` on the x axis leave the lens, found on a grid and padded by one
+    /// grid cell.
+    fn exit_pupil(&self, r0: f32, r1: f32) -> PupilBounds {
+        const FILM_POINTS: usize = 8;
+        const GRID: usize = 32;
+        let extent = 1.5 * self.elements.last().unwrap().aperture_radius;
+        let cell = 2.0 * extent / GRID as f32;
+        let rear_z = self.rear_z();
+
+        let mut bounds = PupilBounds::EMPTY;
+        for i in 0..FILM_POINTS {
+            let film = Point3::build(r0 + (i as f32 + 0.5) / FILM_POINTS as f32 * (r1 - r0), 0.0, 0.0);
+            for a in 0..GRID {
+                for b in 0..GRID {
+                    let (x, y) = (-extent + (a as f32 + 0.5) * cell, -extent + (b as f32 + 0.5) * cell);
+                    let ray = Ray::new(&film, &(Point3::build(x, y, rear_z) - film));
+                    if self.trace_from_film(&ray).is_some() {
+                        bounds.expand(x, y);
+                    }
+                }
+            }
+        }
+        if !bounds.is_empty() {
+            bounds.expand(bounds.min.0 - cell, bounds.min.1 - cell);
+            bounds.expand(bounds.max.0 + cell, bounds.max.1 + cell);
+        }
+        bounds
+    }
+}
+
+/// Where the ray `r_out`, which entered the lens as `r_in` parallel to the axis, crosses the
+/// principal plane and the axis.
+fn cardinal_point(r_in: &Ray, r_out: &Ray) -> (f32, f32) {
+    let (o, d) = (r_out.origin(), r_out.direction());
+    let focal = r_out.at(-o.x / d.x).z;
+    let principal = r_out.at((r_in.origin().x - o.x) / d.x).z;
+    (principal, focal)
+}
+
+/// Refracts `direction` at a surface with `normal` facing against it, `None` on total
+/// internal reflection.
+fn refract(direction: &Vec3, normal: &Vec3, eta: f32) -> Option<Vec3> {
+    let d = Vec3::normalize(direction);
+    let cos_i = -Vec3::dot(&d, normal);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(d * eta + *normal * (eta * cos_i - cos_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOUBLE_GAUSS: &str = include_str!("../lenses/double_gauss_50mm.txt");
+
+    fn double_gauss() -> RealisticLens {
+        let mut lens = RealisticLens::new(RealisticLens::parse(DOUBLE_GAUSS).unwrap(), 43.27);
+        assert!(lens.focus(2.0));
+        lens
+    }
+
+    #[test]
+    fn test_parse_prescription() {
+        let elements = RealisticLens::parse(DOUBLE_GAUSS).unwrap();
+        assert_eq!(elements.len(), 11);
+        assert_eq!(elements[0], LensElement { curvature_radius: 29.475, thickness: 3.76, ior: 1.67, aperture_radius: 12.6 });
+        assert!(elements[5].is_stop());
+
+        assert!(RealisticLens::parse("# nothing\n").is_err());
+        assert!(RealisticLens::parse("29.475 3.76 1.67\n").is_err());
+        assert!(RealisticLens::parse("29.475 3.76 glass 25.2\n").is_err());
+    }
+
+    #[test]
+    fn test_autofocus_converges_on_the_focus_distance() {
+        let lens = double_gauss();
+        let focal_length = lens.focal_length().unwrap();
+        assert!((focal_length - 50.0).abs() < 2.0, "focal length {}", focal_length);
+
+        // Rays from the film center through different parts of the pupil meet again 2 m away
+        for lens_sample in [(0.3, 0.5), (0.5, 0.7), (0.6, 0.4)] {
+            let (origin, direction, _) = lens.ray(50.0, 50.0, 100, 100, lens_sample).unwrap();
+            let focus = origin + direction * ((-2.0 - origin.z) / direction.z);
+            assert!(focus.x.abs() < 1e-3 && focus.y.abs() < 1e-3, "{:?}", (focus.x, focus.y));
+        }
+        let mut too_close = double_gauss();
+        assert!(!too_close.focus(0.01));
+        assert_eq!(too_close.elements(), lens.elements());
+    }
+
+    #[test]
+    fn test_corners_vignette_and_image_is_upright() {
+        let lens = double_gauss();
+        let (_, center, center_weight) = lens.ray(50.0, 50.0, 100, 100, (0.5, 0.5)).unwrap();
+        assert!(center.z < 0.0);
+        assert!((center_weight - 1.0).abs() < 0.1);
+
+        // The top right corner of the image looks up and to the right, with less light
+        let (_, corner, corner_weight) = lens.ray(99.0, 1.0, 100, 100, (0.5, 0.5)).unwrap();
+        assert!(corner.x > 0.0 && corner.y > 0.0);
+        assert!(corner_weight < center_weight);
+
+        let mut stopped_down = double_gauss();
+        stopped_down.set_aperture_diameter(4.0);
+        assert!(stopped_down.exit_pupils[0].area() < lens.exit_pupils[0].area());
+    }
+
+    #[test]
+    fn test_no_rays_without_an_axial_pupil() {
+        // Light reaching the corners can't be weighted against the center without any there
+        let mut lens = double_gauss();
+        assert!(lens.ray(99.0, 1.0, 100, 100, (0.5, 0.5)).is_some());
+        lens.exit_pupils[0] = PupilBounds { min: (0.0, -1.0), max: (0.0, 1.0) };
+        assert!(lens.ray(99.0, 1.0, 100, 100, (0.5, 0.5)).is_none());
+        lens.exit_pupils[0] = PupilBounds::EMPTY;
+        assert!(lens.ray(99.0, 1.0, 100, 100, (0.5, 0.5)).is_none());
+    }
+}
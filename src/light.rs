@@ -0,0 +1,224 @@
+use std::f32::consts::PI;
+
+use crate::color::Color;
+use crate::onb::Onb;
+use crate::vec3::{Point3, Vec3};
+use crate::sampler::Sampler;
+use raytracing::degrees_to_radians;
+
+/// Lights without a surface. Random rays can never hit them, so they only contribute
+/// through the shadow rays traced from `Camera::ray_color`.
+#[derive(Copy, Clone)]
+pub enum Light {
+    Point(PointLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight),
+}
+
+/// Direction and strength of the light arriving at a point.
+pub struct LightSample {
+    /// Unit vector from the shaded point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: f32,
+    /// Radiance arriving along `direction`, already divided by the sampling density, so it
+    /// only has to be multiplied with the BSDF and cosine.
+    pub radiance: Color,
+}
+
+impl Light {
+    /// Samples the light as seen from `point`, `None` if no light reaches it.
+    pub fn sample(&self, point: &Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        match self {
+            Light::Point(light) => light.sample(point),
+            Light::Spot(light) => light.sample(point),
+            Light::Directional(light) => light.sample(point, sampler),
+        }
+    }
+
+    /// Every value the light's emission depends on, tagged with its kind. Used by
+    /// `Camera::fingerprint` to tell lights apart.
+    pub fn parameters(&self) -> Vec<f32> {
+        let v = |v: &Vec3| [v.x, v.y, v.z];
+        match self {
+            Light::Point(light) => [[0.0].as_slice(), &v(&light.position), &v(&light.intensity)].concat(),
+            Light::Spot(light) => {
+                [[1.0].as_slice(), &v(&light.position), &v(&light.intensity), &v(&light.direction), &[light.cos_inner, light.cos_outer]].concat()
+            }
+            Light::Directional(light) => [[2.0].as_slice(), &v(&light.direction), &v(&light.irradiance), &[light.cos_half_angle]].concat(),
+        }
+    }
+}
+
+/// Light radiating equally in all directions from a single point.
+#[derive(Copy, Clone)]
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self { position, intensity }
+    }
+
+    pub fn sample(&self, point: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / distance_squared,
+        })
+    }
+}
+
+/// Point light restricted to a cone. Full intensity inside `inner_angle`, fading smoothly
+/// to nothing at `outer_angle`.
+#[derive(Copy, Clone)]
+pub struct SpotLight {
+    pub position: Point3,
+    pub intensity: Color,
+    direction: Vec3,
+    cos_inner: f32,
+    cos_outer: f32,
+}
+
+impl SpotLight {
+    /// Angles are the half angles of the cones in degrees.
+    pub fn new(position: Point3, target: Point3, intensity: Color, inner_angle: f32, outer_angle: f32) -> Self {
+        let outer_angle = outer_angle.max(inner_angle);
+        Self {
+            position,
+            intensity,
+            direction: Vec3::normalize(&(target - position)),
+            cos_inner: degrees_to_radians(inner_angle).cos(),
+            cos_outer: degrees_to_radians(outer_angle).cos(),
+        }
+    }
+
+    /// Fraction of the intensity emitted towards `direction` (pointing away from the light).
+    pub fn falloff(&self, direction: &Vec3) -> f32 {
+        let cos_theta = Vec3::dot(&self.direction, direction);
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    pub fn sample(&self, point: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+
+        let falloff = self.falloff(&-direction);
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * (falloff / distance_squared),
+        })
+    }
+}
+
+/// Light from infinitely far away such as the sun. With an `angular_diameter` above zero it
+/// is a disk of directions instead of a single one, giving soft shadows.
+#[derive(Copy, Clone)]
+pub struct DirectionalLight {
+    /// Unit vector pointing towards the light.
+    pub direction: Vec3,
+    /// Irradiance on a surface facing the light.
+    pub irradiance: Color,
+    cos_half_angle: f32,
+}
+
+impl DirectionalLight {
+    /// `direction` points towards the light, `angular_diameter` is in degrees
+    /// (the sun is about 0.53).
+    pub fn new(direction: Vec3, irradiance: Color, angular_diameter: f32) -> Self {
+        Self {
+            direction: Vec3::normalize(&direction),
+            irradiance,
+            cos_half_angle: degrees_to_radians(angular_diameter.max(0.0) * 0.5).cos(),
+        }
+    }
+
+    pub fn sample(&self, _point: &Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let direction = if self.cos_half_angle >= 1.0 {
+            self.direction
+        } else {
+            // Uniform over the cone: radiance is irradiance / solid angle and the density is
+            // 1 / solid angle, so the weighted radiance stays equal to the irradiance
+            let (u1, u2) = sampler.get_2d();
+            let z = 1.0 - u1 * (1.0 - self.cos_half_angle);
+            let phi = 2.0 * PI * u2;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            Onb::new(&self.direction).transform(&Vec3::build(r * phi.cos(), r * phi.sin(), z))
+        };
+
+        Some(LightSample {
+            direction,
+            distance: f32::INFINITY,
+            radiance: self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    #[test]
+    fn test_point_light_inverse_square() {
+        let light = PointLight::new(Point3::build(0.0, 2.0, 0.0), Color::build(4.0, 4.0, 4.0));
+        let sample = light.sample(&Point3::new()).unwrap();
+        assert_eq!(sample.distance, 2.0);
+        assert_eq!(sample.direction, Vec3::build(0.0, 1.0, 0.0));
+        assert_eq!(sample.radiance, Color::build(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = SpotLight::new(
+            Point3::build(0.0, 1.0, 0.0),
+            Point3::new(),
+            Color::build(1.0, 1.0, 1.0),
+            20.0,
+            30.0,
+        );
+        // Straight below is inside the inner cone
+        assert_eq!(light.sample(&Point3::new()).unwrap().radiance.x, 1.0);
+        // 45 degrees off axis is outside the outer cone
+        assert!(light.sample(&Point3::build(1.0, 0.0, 0.0)).is_none());
+        // 25 degrees is in the falloff region
+        let falloff = light.falloff(&Vec3::normalize(&Vec3::build(degrees_to_radians(25.0).tan(), -1.0, 0.0)));
+        assert!(falloff > 0.0 && falloff < 1.0);
+    }
+
+    #[test]
+    fn test_directional_light_samples_within_disk() {
+        let light = DirectionalLight::new(Vec3::build(0.0, 1.0, 0.0), Color::build(1.0, 1.0, 1.0), 10.0);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let sample = light.sample(&Point3::new(), &mut sampler).unwrap();
+            assert!(Vec3::dot(&sample.direction, &light.direction) >= degrees_to_radians(5.0).cos() - 1e-5);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::time::Duration;
 
 // modules
 mod vec3;
@@ -10,17 +11,405 @@ mod sphere;
 mod interval;
 mod camera;
 mod material;
+mod sdf;
+mod onb;
+mod constant_medium;
+mod aabb;
+mod noise;
+mod volume;
+mod quad;
+mod triangle;
+mod scene;
+mod pdf;
+mod light;
+mod distribution;
+mod environment;
+mod sky;
+mod sampler;
+mod framebuffer;
+mod checkpoint;
+mod tile;
+mod progress;
+mod stats;
+mod aov;
+mod denoise;
+mod filter;
+mod projection;
+mod lens;
+mod aperture;
+mod animation;
 
 use crate::vec3::*;
-use crate::hittable_list::{HittableList};
+use crate::color::Color;
 use crate::sphere::Sphere;
-use crate::camera::{Camera};
-use crate::material::{Material, Lambertian, Metal};
+use crate::quad::{Quad, make_box};
+use crate::camera::{AdaptiveSampling, Camera, Checkpointing, DepthOfField, Progressive, Stereo, StereoLayout};
+use crate::material::{Material, Lambertian, Metal, Glossy, Isotropic, DiffuseLight, HenyeyGreenstein};
+use crate::sdf::{Displace, Intersection, Mandelbulb, Repeat, RoundBox, Scale, SdfBox, SdfHittable, SdfPlane, SdfSphere, SmoothIntersection, SmoothSubtraction, SmoothUnion, Subtraction, Torus, Translate, Twist, Union};
+use crate::constant_medium::ConstantMedium;
+use crate::triangle::Triangle;
+use crate::aabb::Aabb;
+use crate::volume::{DenseGrid, HeterogeneousMedium, SparseGrid};
+use crate::scene::{Scene, Background};
+use crate::light::{Light, PointLight, SpotLight};
+use crate::pdf::MisHeuristic;
+use crate::sampler::SamplerType;
+use crate::environment::EnvironmentMap;
+use crate::sky::PreethamSky;
+use crate::tile::TileOrder;
+use crate::aov::Aov;
+use crate::denoise::Denoiser;
+use crate::filter::{FilterKind, PixelFilter};
+use crate::projection::Projection;
+use crate::lens::RealisticLens;
+use crate::aperture::{Aperture, ApertureImage};
+use crate::animation::{Animated, CameraAnimation, Clock, Interpolation, Keyframe, Sequence, Track};
 
 fn main() {
-    let camera = Camera::new(16.0 / 9.0, 500);
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let seed = take_option(&mut args, "--seed")
+        .map(|value| value.parse().expect("--seed expects an unsigned integer"))
+        .unwrap_or(0);
+    let sampler = take_option(&mut args, "--sampler").map(|value| match value.as_str() {
+        "independent" => SamplerType::Independent,
+        "stratified" => SamplerType::Stratified,
+        "halton" => SamplerType::Halton,
+        "sobol" => SamplerType::Sobol,
+        "bluenoise" => SamplerType::BlueNoise,
+        _ => panic!("--sampler expects independent, stratified, halton, sobol or bluenoise"),
+    });
+    let mis_heuristic = take_option(&mut args, "--mis").map(|value| match value.as_str() {
+        "balance" => MisHeuristic::Balance,
+        "power" => MisHeuristic::Power,
+        _ => panic!("--mis expects balance or power"),
+    });
+    let adaptive_threshold: Option<f32> = take_option(&mut args, "--adaptive")
+        .map(|value| value.parse().expect("--adaptive expects the relative error to stop at"));
+    let heatmap = take_flag(&mut args, "--heatmap");
+    // Any of the progressive limits switches to progressive rendering
+    let time_budget: Option<f32> = take_option(&mut args, "--time-budget")
+        .map(|value| value.parse().expect("--time-budget expects seconds"));
+    let target_samples: Option<u32> = take_option(&mut args, "--target-samples")
+        .map(|value| value.parse().expect("--target-samples expects a sample count"));
+    let noise_threshold: Option<f32> = take_option(&mut args, "--noise-threshold")
+        .map(|value| value.parse().expect("--noise-threshold expects the average relative error to stop at"));
+    let checkpoint = take_option(&mut args, "--checkpoint");
+    let checkpoint_interval: Option<f32> = take_option(&mut args, "--checkpoint-interval")
+        .map(|value| value.parse().expect("--checkpoint-interval expects seconds"));
+    let resume = take_flag(&mut args, "--resume");
+    let print_stats = take_flag(&mut args, "--stats");
+    let stats_json = take_option(&mut args, "--stats-json");
+    let denoise = take_flag(&mut args, "--denoise");
+    let denoise_strength: Option<f32> = take_option(&mut args, "--denoise-strength")
+        .map(|value| value.parse().expect("--denoise-strength expects a factor, 1 is the default"));
+    let filter_kind = take_option(&mut args, "--filter").map(|value| match value.as_str() {
+        "box" => FilterKind::Box,
+        "tent" => FilterKind::Tent,
+        "gaussian" => FilterKind::Gaussian,
+        "mitchell" => FilterKind::Mitchell,
+        "lanczos" => FilterKind::Lanczos,
+        _ => panic!("--filter expects box, tent, gaussian, mitchell or lanczos"),
+    });
+    let filter_radius: Option<f32> = take_option(&mut args, "--filter-radius")
+        .map(|value| value.parse().expect("--filter-radius expects a radius in pixels"));
+    let aovs: Vec<Aov> = match take_option(&mut args, "--aovs").as_deref() {
+        None => Vec::new(),
+        Some("all") => Aov::ALL.to_vec(),
+        Some(names) => names
+            .split(',')
+            .map(|name| Aov::from_name(name).unwrap_or_else(|| panic!("Unknown AOV {}", name)))
+            .collect(),
+    };
+    let tile_size: Option<u32> = take_option(&mut args, "--tile-size")
+        .map(|value| value.parse().expect("--tile-size expects a size in pixels"));
+    let tile_order = take_option(&mut args, "--tile-order").map(|value| match value.as_str() {
+        "row" => TileOrder::Row,
+        "spiral" => TileOrder::Spiral,
+        "hilbert" => TileOrder::Hilbert,
+        _ => panic!("--tile-order expects row, spiral or hilbert"),
+    });
 
-    let mut world = HittableList::new();
+    let fov: Option<f32> = take_option(&mut args, "--fov")
+        .map(|value| value.parse().expect("--fov expects an angle in degrees"));
+    let view_height: Option<f32> = take_option(&mut args, "--view-height")
+        .map(|value| value.parse().expect("--view-height expects the height of an orthographic view"));
+    let projection = take_option(&mut args, "--projection").map(|value| match value.as_str() {
+        "perspective" => Projection::Perspective { vfov: fov.unwrap_or(90.0) },
+        "orthographic" => Projection::Orthographic { height: view_height.unwrap_or(2.0) },
+        "fisheye" => Projection::FisheyeEquidistant { fov: fov.unwrap_or(180.0) },
+        "equisolid" => Projection::FisheyeEquisolid { fov: fov.unwrap_or(180.0) },
+        "equirectangular" => Projection::Equirectangular,
+        _ => panic!("--projection expects perspective, orthographic, fisheye, equisolid or equirectangular"),
+    });
+    let lookfrom = take_option(&mut args, "--lookfrom").map(|value| parse_point(&value, "--lookfrom"));
+    let lookat = take_option(&mut args, "--lookat").map(|value| parse_point(&value, "--lookat"));
+    let stereo_layout = take_option(&mut args, "--stereo").map(|value| match value.as_str() {
+        "side-by-side" => StereoLayout::SideBySide,
+        "over-under" => StereoLayout::OverUnder,
+        _ => panic!("--stereo expects side-by-side or over-under"),
+    });
+    let interocular: Option<f32> = take_option(&mut args, "--interocular")
+        .map(|value| value.parse().expect("--interocular expects the distance between the eyes"));
+    let convergence: Option<f32> = take_option(&mut args, "--convergence")
+        .map(|value| value.parse().expect("--convergence expects the distance the eyes converge at"));
+    let lens = take_option(&mut args, "--lens");
+    let film_diagonal: f32 = take_option(&mut args, "--film-diagonal")
+        .map(|value| value.parse().expect("--film-diagonal expects millimetres"))
+        .unwrap_or(43.27);
+    let focus_distance: Option<f32> = take_option(&mut args, "--focus-distance")
+        .map(|value| value.parse().expect("--focus-distance expects the distance from the film"));
+    let aperture: Option<f32> = take_option(&mut args, "--aperture")
+        .map(|value| value.parse().expect("--aperture expects a diameter in millimetres"));
+    let aperture_radius: Option<f32> = take_option(&mut args, "--aperture-radius")
+        .map(|value| value.parse().expect("--aperture-radius expects the radius of a thin lens"));
+    let blades: Option<u32> = take_option(&mut args, "--blades")
+        .map(|value| value.parse().expect("--blades expects the number of aperture blades"));
+    let blade_rotation: f32 = take_option(&mut args, "--blade-rotation")
+        .map(|value| value.parse().expect("--blade-rotation expects degrees"))
+        .unwrap_or(0.0);
+    let aperture_image = take_option(&mut args, "--aperture-image");
+    let cat_eye: Option<f32> = take_option(&mut args, "--cat-eye")
+        .map(|value| value.parse().expect("--cat-eye expects a strength between 0 and 1"));
+    let frames = take_option(&mut args, "--frames").map(|value| {
+        let (first, last) = value.split_once("..").expect("--frames expects first..last");
+        let frame = |f: &str| f.parse::<u32>().expect("--frames expects first..last");
+        (frame(first), frame(last))
+    });
+    let frame_rate: Option<f32> = take_option(&mut args, "--frame-rate")
+        .map(|value| value.parse().expect("--frame-rate expects frames per second"));
+    let turntable: Option<f32> = take_option(&mut args, "--turntable")
+        .map(|value| value.parse().expect("--turntable expects the seconds one turn takes"));
+    let output = take_option(&mut args, "--output");
+    let volume = take_option(&mut args, "--volume");
+    let save_volume = take_option(&mut args, "--save-volume");
+
+    // Animated objects in the scenes follow this
+    let clock = Clock::new();
+    let (mut camera, scene) = match args.first().map(String::as_str) {
+        Some("cornell") => (Camera::new(1.0, 400), cornell_box()),
+        Some("fog") => (Camera::new(1.0, 400), foggy_cornell_box()),
+        Some("sdf") => (Camera::new(16.0 / 9.0, 500), implicit_shapes()),
+        Some("hdri") => {
+            let path = args.get(1).expect("Usage: raytracing hdri <environment.hdr>");
+            let env = EnvironmentMap::load(path, 1.0, 0.0).expect("Failed to load environment map");
+
+            let mut scene = spheres();
+            scene.background = Background::Environment(Rc::new(env));
+            (Camera::new(16.0 / 9.0, 500), scene)
+        }
+        Some("sky") => {
+            let mut scene = spheres();
+            scene.set_sky(PreethamSky::new(Vec3::build(1.0, 0.6, -0.5), 3.0), 3.0);
+            (Camera::new(16.0 / 9.0, 500), scene)
+        }
+        Some("bounce") => (Camera::new(16.0 / 9.0, 500), bouncing_spheres(&clock)),
+        Some("cloud") => {
+            let grid = match &volume {
+                Some(path) => DenseGrid::load_raw(path).expect("Failed to load volume"),
+                None => DenseGrid::cloud(64, 3.0, 5, 7),
+            };
+            if let Some(path) = &save_volume {
+                grid.save_raw(path).expect("Failed to save volume");
+            }
+            (Camera::new(16.0 / 9.0, 500), cloud(grid))
+        }
+        _ => (Camera::new(16.0 / 9.0, 500), spheres()),
+    };
+
+    camera.seed = seed;
+    if let Some(sampler) = sampler {
+        camera.sampler = sampler;
+    }
+    if let Some(mis_heuristic) = mis_heuristic {
+        camera.mis_heuristic = mis_heuristic;
+    }
+    if let Some(projection) = projection.or(fov.map(|vfov| Projection::Perspective { vfov })) {
+        camera.projection = projection;
+    }
+    if let Some(layout) = stereo_layout {
+        let mut stereo = Stereo::new(layout);
+        stereo.interocular = interocular.unwrap_or(stereo.interocular);
+        stereo.convergence = convergence.unwrap_or(stereo.convergence);
+        camera.stereo = Some(stereo);
+    }
+    let lookfrom = lookfrom.unwrap_or_else(Point3::new);
+    let lookat = lookat.unwrap_or(lookfrom - Vec3::build(0.0, 0.0, 1.0));
+    camera.look_at(lookfrom, lookat, Vec3::build(0.0, 1.0, 0.0));
+    if let Some(radius) = aperture_radius {
+        let mut depth_of_field = DepthOfField::new(radius, focus_distance.unwrap_or((lookat - lookfrom).length()));
+        if let Some(path) = aperture_image {
+            depth_of_field.aperture = Aperture::Image(Rc::new(ApertureImage::load(path).expect("Failed to load aperture image")));
+        } else if let Some(blades) = blades {
+            depth_of_field.aperture = Aperture::Polygon { blades, rotation: blade_rotation };
+        }
+        depth_of_field.cat_eye = cat_eye.unwrap_or(0.0);
+        camera.depth_of_field = Some(depth_of_field);
+    }
+    if let Some(path) = lens {
+        let mut lens = RealisticLens::load(&path, film_diagonal).expect("Failed to load lens");
+        if let Some(diameter) = aperture {
+            lens.set_aperture_diameter(diameter);
+        }
+        // Focus on what the camera looks at unless told otherwise
+        let distance = focus_distance.unwrap_or((lookat - lookfrom).length());
+        if !lens.focus(distance) {
+            panic!("{} can't focus at {}", path, distance);
+        }
+        camera.lens = Some(lens);
+    }
+    camera.aovs = aovs;
+    if let Some(kind) = filter_kind {
+        camera.filter = match filter_radius {
+            Some(radius) => PixelFilter::with_radius(kind, radius),
+            None => PixelFilter::new(kind),
+        };
+    }
+    if denoise || denoise_strength.is_some() {
+        camera.denoiser = Some(Denoiser::with_strength(denoise_strength.unwrap_or(1.0)));
+    }
+    if let Some(tile_size) = tile_size {
+        camera.tile_size = tile_size;
+    }
+    if let Some(tile_order) = tile_order {
+        camera.tile_order = tile_order;
+    }
+    if let Some(threshold) = adaptive_threshold {
+        let mut adaptive = AdaptiveSampling::new(threshold);
+        adaptive.heatmap = heatmap;
+        camera.adaptive = Some(adaptive);
+    }
+    if time_budget.is_some() || target_samples.is_some() || noise_threshold.is_some() {
+        let mut progressive = Progressive::new(4);
+        progressive.time_budget = time_budget.map(Duration::from_secs_f32);
+        progressive.target_samples = target_samples;
+        progressive.noise_threshold = noise_threshold;
+        camera.progressive = Some(progressive);
+    }
+    if let Some(path) = checkpoint {
+        let mut checkpointing = Checkpointing::new(path);
+        checkpointing.resume = resume;
+        if let Some(interval) = checkpoint_interval {
+            checkpointing.interval = Duration::from_secs_f32(interval);
+        }
+        camera.checkpoint = Some(checkpointing);
+    }
+    let mut animation = CameraAnimation::default();
+    if let Some(seconds) = turntable {
+        animation.position = Some(orbit(lookfrom, lookat, seconds));
+        animation.target = Some(Track::new(Interpolation::Linear).with_key(0.0, lookat));
+    }
+
+    let summaries = match frames {
+        Some((first, last)) => {
+            let mut sequence = Sequence::new(first, last, output.unwrap_or_else(|| String::from("frame_####.png")));
+            sequence.frame_rate = frame_rate.unwrap_or(sequence.frame_rate);
+            sequence.render(&mut camera, &animation, &clock, &scene)
+        }
+        None => {
+            if let Some(path) = output {
+                camera.output_path = path.into();
+            }
+            camera.render(&scene).map(|summary| vec![summary])
+        }
+    };
+    let summaries = summaries.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    for (frame, summary) in summaries.iter().enumerate() {
+        if print_stats {
+            print!("{}", summary.stats.summary());
+        }
+        if let Some(path) = &stats_json {
+            // One file per frame of a sequence
+            let path = if summaries.len() > 1 { format!("{}.{}", path, frame) } else { path.clone() };
+            std::fs::write(&path, summary.stats.to_json()).expect("Failed to write render statistics");
+        }
+    }
+}
+
+/// Track circling `lookfrom` around the vertical axis through `lookat` once in `seconds`.
+fn orbit(lookfrom: Point3, lookat: Point3, seconds: f32) -> Track<Point3> {
+    let offset = lookfrom - lookat;
+    let mut track = Track::new(Interpolation::CatmullRom);
+    // Catmull-Rom through 16 points on the circle stays within a fraction of a percent of it
+    for i in 0..=16 {
+        let angle = 2.0 * std::f32::consts::PI * i as f32 / 16.0;
+        let (sin, cos) = angle.sin_cos();
+        let position = lookat + Vec3::build(cos * offset.x + sin * offset.z, offset.y, -sin * offset.x + cos * offset.z);
+        track.add(Keyframe::new(seconds * i as f32 / 16.0, position));
+    }
+    track
+}
+
+/// Removes the flag `name` from `args`, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != name);
+    args.len() != len
+}
+
+/// Removes `name` and the value following it from `args`, returning the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    let value = args.get(index + 1).cloned().unwrap_or_else(|| panic!("{} expects a value", name));
+    args.drain(index..=index + 1);
+    Some(value)
+}
+
+/// Parses a point given as `x,y,z`.
+fn parse_point(value: &str, name: &str) -> Point3 {
+    let coordinates: Vec<f32> = value
+        .split(',')
+        .map(|c| c.trim().parse().unwrap_or_else(|_| panic!("{} expects x,y,z", name)))
+        .collect();
+    match coordinates[..] {
+        [x, y, z] => Point3::build(x, y, z),
+        _ => panic!("{} expects x,y,z", name),
+    }
+}
+
+/// `spheres` with the center sphere bouncing on the ground once a second.
+fn bouncing_spheres(clock: &Clock) -> Scene {
+    let mut world = spheres();
+    let ball = Sphere::new(&Point3::build(0.0, 0.0, 0.0), 0.3, Material::Lambertian(Lambertian::new(Vec3::build(0.7, 0.1, 0.1))));
+    let mut animated = Animated::new(Rc::new(ball), clock.clone());
+    // Default handles ease into the top of every bounce, handles pointing up make it leave the
+    // ground as fast as it arrived
+    let mut height = Track::new(Interpolation::Bezier);
+    for bounce in 0..10 {
+        let time = bounce as f32;
+        let mut ground = Keyframe::new(time, Vec3::build(-0.3, -0.2, -0.3));
+        ground.out_handle = Vec3::build(-0.3, 0.4, -0.3);
+        ground.in_handle = Vec3::build(-0.3, 0.4, -0.3);
+        height.add(ground);
+        height.add(Keyframe::new(time + 0.5, Vec3::build(-0.3, 0.6, -0.3)));
+    }
+    animated.translation = Some(height);
+    animated.rotation_y = Some(Track::new(Interpolation::Linear).with_key(0.0, 0.0).with_key(10.0, 720.0));
+    world.add(Rc::new(animated));
+    world
+}
+
+/// Voxel grid volume floating over the ground, lit by the sun of an analytic sky so shadow
+/// rays pass through it.
+fn cloud(grid: DenseGrid) -> Scene {
+    let mut scene = Scene::new();
+    let ground = Material::Lambertian(Lambertian::new(Vec3::build(0.8, 0.8, 0.8)));
+    scene.add(Rc::new(Sphere::new(&Point3::build(0.0, -100.5, -1.0), 100.0, ground)));
+
+    // Clouds are mostly empty space, only the bricks holding density are kept
+    let grid = SparseGrid::from_dense(&grid, 8);
+    let bounds = Aabb::new(&Point3::build(-1.2, -0.3, -2.6), &Point3::build(1.2, 0.9, -1.0));
+    let phase_function = Material::HenyeyGreenstein(HenyeyGreenstein::new(Color::build(0.95, 0.95, 0.95), 0.6));
+    scene.add(Rc::new(HeterogeneousMedium::new(grid, bounds, 12.0, phase_function)));
+
+    scene.set_sky(PreethamSky::new(Vec3::build(1.0, 0.8, -0.3), 3.0), 3.0);
+    scene
+}
+
+fn spheres() -> Scene {
+    let mut world = Scene::new();
 
     let ground_material = Material::Lambertian(Lambertian::new(Vec3::build(0.8, 0.8, 0.8)));
     let material_center = Material::Lambertian(Lambertian::new(Vec3::build(0.1, 0.2, 0.5)));
@@ -37,6 +426,143 @@ fn main() {
     world.add(Rc::new(sphere3));
     world.add(Rc::new(sphere4));
 
-    camera.render(&world);
+    world
+}
+
+/// Shapes without a closed form intersection, sphere traced through their distance fields and
+/// lit by a point light and a spotlight.
+fn implicit_shapes() -> Scene {
+    let mut scene = Scene::new();
+    let stone = Material::Lambertian(Lambertian::new(Color::build(0.6, 0.6, 0.55)));
+    let blue = Material::Lambertian(Lambertian::new(Color::build(0.1, 0.2, 0.5)));
+    let gold = Material::Glossy(Glossy::new(Color::build(0.8, 0.6, 0.3), 200.0));
+    let copper = Material::Glossy(Glossy::new(Color::build(0.8, 0.4, 0.3), 50.0));
+
+    // Ground with a bed of pebbles in front of the shapes
+    let pebbles = Intersection {
+        a: Repeat { inner: SdfSphere::new(Point3::build(0.0, -0.5, 0.0), 0.08), period: Vec3::build(0.35, 0.0, 0.35) },
+        b: SdfBox::new(Point3::build(0.0, -0.5, -2.5), Vec3::build(2.5, 0.2, 1.5)),
+    };
+    let ground = Union { a: SdfPlane::new(Vec3::build(0.0, 1.0, 0.0), -0.5), b: pebbles };
+    scene.add(Rc::new(SdfHittable::new(ground, stone)));
+
+    scene.add(Rc::new(SdfHittable::new(Mandelbulb::new(Point3::build(0.0, 0.05, -2.0), 0.5), gold)));
+
+    let ring = SmoothUnion {
+        a: Torus::new(Point3::build(-1.3, -0.2, -2.0), 0.35, 0.1),
+        b: SdfSphere::new(Point3::build(-1.3, 0.05, -2.0), 0.22),
+        k: 0.2,
+    };
+    scene.add(Rc::new(SdfHittable::new(ring, blue)));
+
+    // Rounded die with a square hole drilled through it
+    let center = Point3::build(1.3, -0.15, -2.0);
+    let die = Subtraction {
+        a: SmoothIntersection {
+            a: RoundBox::new(center, Vec3::build(0.3, 0.3, 0.3), 0.05),
+            b: SdfSphere::new(center, 0.4),
+            k: 0.05,
+        },
+        b: SdfBox::new(center, Vec3::build(0.1, 0.1, 0.5)),
+    };
+    scene.add(Rc::new(SdfHittable::new(die, copper)));
+
+    // Twisting and displacing break the distance bound, so these march in shorter steps
+    let column = Translate {
+        inner: Twist {
+            inner: SmoothSubtraction {
+                a: SdfBox::new(Point3::new(), Vec3::build(0.2, 0.5, 0.2)),
+                b: SdfSphere::new(Point3::new(), 0.25),
+                k: 0.05,
+            },
+            rate: 2.0,
+        },
+        offset: Vec3::build(-0.7, 0.0, -3.2),
+    };
+    let mut column = SdfHittable::new(column, stone);
+    column.step_scale = 0.5;
+    scene.add(Rc::new(column));
+
+    let ripples = |p: &Point3| 0.05 * (10.0 * p.x).sin() * (10.0 * p.y).sin() * (10.0 * p.z).sin();
+    let blob = Translate {
+        inner: Scale { inner: Displace { inner: SdfSphere::new(Point3::new(), 1.0), displacement: ripples }, factor: 0.4 },
+        offset: Vec3::build(0.7, -0.1, -3.2),
+    };
+    let mut blob = SdfHittable::new(blob, blue);
+    blob.step_scale = 0.5;
+    scene.add(Rc::new(blob));
+
+    scene.add_punctual_light(Light::Point(PointLight::new(Point3::build(-1.5, 1.5, -1.0), Color::build(3.0, 3.0, 3.0))));
+    scene.add_punctual_light(Light::Spot(SpotLight::new(
+        Point3::build(1.5, 2.5, -0.5),
+        Point3::build(0.0, 0.0, -2.0),
+        Color::build(12.0, 11.0, 9.0),
+        15.0,
+        25.0,
+    )));
+
+    scene
+}
+
+/// Walls and ceiling light of `cornell_box`, without anything inside.
+fn cornell_room() -> Scene {
+    let mut scene = Scene::new();
+    scene.background = Background::Solid(Color::new());
+
+    let red = Material::Lambertian(Lambertian::new(Color::build(0.65, 0.05, 0.05)));
+    let white = Material::Lambertian(Lambertian::new(Color::build(0.73, 0.73, 0.73)));
+    let green = Material::Lambertian(Lambertian::new(Color::build(0.12, 0.45, 0.15)));
+    let light = Material::DiffuseLight(DiffuseLight::new(Color::build(15.0, 15.0, 15.0)));
+
+    let x = Vec3::build(2.0, 0.0, 0.0);
+    let y = Vec3::build(0.0, 2.0, 0.0);
+    let z = Vec3::build(0.0, 0.0, 2.0);
+
+    scene.add(Rc::new(Quad::new(&Point3::build(1.0, -1.0, -3.0), &y, &z, green)));
+    scene.add(Rc::new(Quad::new(&Point3::build(-1.0, -1.0, -3.0), &z, &y, red)));
+    scene.add(Rc::new(Quad::new(&Point3::build(-1.0, -1.0, -3.0), &y, &x, white)));
+    scene.add(Rc::new(Quad::new(&Point3::build(-1.0, -1.0, -3.0), &x, &z, white)));
+    scene.add(Rc::new(Quad::new(&Point3::build(-1.0, 1.0, -3.0), &z, &x, white)));
+
+    // Small light on the ceiling, facing down
+    scene.add_light(Rc::new(Quad::new(
+        &Point3::build(-0.25, 0.999, -2.25),
+        &Vec3::build(0.5, 0.0, 0.0),
+        &Vec3::build(0.0, 0.0, 0.5),
+        light,
+    )));
+
+    scene
+}
+
+/// Cornell box seen through its open front face, the camera at the origin looks down -z
+/// so the box spans x and y in [-1, 1] and z in [-3, -1].
+fn cornell_box() -> Scene {
+    let mut scene = cornell_room();
+    let white = Material::Lambertian(Lambertian::new(Color::build(0.73, 0.73, 0.73)));
+    scene.add(Rc::new(make_box(&Point3::build(-0.6, -1.0, -2.6), &Point3::build(-0.05, 0.2, -2.05), white)));
+    scene.add(Rc::new(make_box(&Point3::build(0.05, -1.0, -1.95), &Point3::build(0.6, -0.45, -1.4), white)));
+    scene
+}
+
+/// `cornell_box` with smoke filling the tall box and a pyramid in place of the short one.
+fn foggy_cornell_box() -> Scene {
+    let mut scene = cornell_room();
+    let smoke = Material::Isotropic(Isotropic::new(Color::build(0.73, 0.73, 0.73)));
+    let boundary = make_box(&Point3::build(-0.6, -1.0, -2.6), &Point3::build(-0.05, 0.2, -2.05), smoke);
+    scene.add(Rc::new(ConstantMedium::new(Rc::new(boundary), 3.0, smoke)));
+
+    let white = Material::Lambertian(Lambertian::new(Color::build(0.73, 0.73, 0.73)));
+    let apex = Point3::build(0.325, -0.3, -1.675);
+    let base = [
+        Point3::build(0.05, -1.0, -1.95),
+        Point3::build(0.6, -1.0, -1.95),
+        Point3::build(0.6, -1.0, -1.4),
+        Point3::build(0.05, -1.0, -1.4),
+    ];
+    for i in 0..4 {
+        scene.add(Rc::new(Triangle::new(&base[(i + 1) % 4], &base[i], &apex, white)));
+    }
+    scene
 }
 
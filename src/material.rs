@@ -2,6 +2,33 @@ use crate::ray::{Ray};
 use crate::hittable::{HitRecord};
 use crate::color::Color;
 use crate::vec3::{Vec3};
+use crate::onb::Onb;
+use crate::sampler::{sample_uniform_sphere, Sampler};
+use std::f32::consts::PI;
+use raytracing::rng::hash_seed;
+
+/// Result of scattering a ray off a material.
+pub struct ScatterRecord {
+    /// Sample weight of the scattered ray, i.e. the BSDF times the cosine divided by `pdf`.
+    pub attenuation: Color,
+    pub scattered: Ray,
+    /// Solid angle density the scattered direction was sampled with, 0 for specular scattering.
+    pub pdf: f32,
+    /// Set for delta distributions (perfect mirrors) which can't be evaluated for arbitrary
+    /// directions, so light sampling is skipped for them.
+    pub is_specular: bool,
+}
+
+impl ScatterRecord {
+    pub fn new() -> Self {
+        Self {
+            attenuation: Color::new(),
+            scattered: Ray::default(),
+            pdf: 0.0,
+            is_specular: false,
+        }
+    }
+}
 
 /// Material structure defines different types of materials that can be applied to hittable
 /// objects.
@@ -9,18 +36,94 @@ use crate::vec3::{Vec3};
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
+    Glossy(Glossy),
+    Isotropic(Isotropic),
+    HenyeyGreenstein(HenyeyGreenstein),
+    DiffuseLight(DiffuseLight),
 }
 
 /// The Material trait provides a method to scatter rays based on the material properties.
 impl Material {
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord, sampler: &mut dyn Sampler) -> bool {
         match self {
             Material::Lambertian(mat) => {
-                mat.scatter(r_in, rec, attenuation, scattered)
+                mat.scatter(r_in, rec, srec, sampler)
             }
             Material::Metal(mat) => {
-                mat.scatter(r_in, rec, attenuation, scattered)
+                mat.scatter(r_in, rec, srec)
+            }
+            Material::Glossy(mat) => {
+                mat.scatter(r_in, rec, srec, sampler)
             }
+            Material::Isotropic(mat) => {
+                mat.scatter(r_in, rec, srec, sampler)
+            }
+            Material::HenyeyGreenstein(mat) => {
+                mat.scatter(r_in, rec, srec, sampler)
+            }
+            Material::DiffuseLight(_) => false,
+        }
+    }
+
+    /// Light emitted by the surface towards the incoming ray.
+    pub fn emitted(&self, rec: &HitRecord) -> Color {
+        match self {
+            Material::DiffuseLight(mat) => mat.emitted(rec),
+            _ => Color::new(),
+        }
+    }
+
+    /// Evaluates the BSDF times the cosine term for light arriving from `direction` and leaving
+    /// towards the origin of `r_in`. This is what explicit light sampling multiplies the light's
+    /// radiance with. Specular materials return black since they can't be hit by a sampled
+    /// direction.
+    pub fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        match self {
+            Material::Lambertian(mat) => mat.eval(rec, direction),
+            Material::Glossy(mat) => mat.eval(r_in, rec, direction),
+            Material::Isotropic(mat) => mat.eval(),
+            Material::HenyeyGreenstein(mat) => mat.eval(r_in, direction),
+            Material::Metal(_) | Material::DiffuseLight(_) => Color::new(),
+        }
+    }
+
+    /// Base color of the material, written to the albedo AOV. Lights report their emission
+    /// scaled to a maximum component of 1.
+    pub fn albedo(&self) -> Color {
+        match self {
+            Material::Lambertian(Lambertian { albedo })
+            | Material::Metal(Metal { albedo })
+            | Material::Glossy(Glossy { albedo, .. })
+            | Material::Isotropic(Isotropic { albedo })
+            | Material::HenyeyGreenstein(HenyeyGreenstein { albedo, .. }) => *albedo,
+            Material::DiffuseLight(DiffuseLight { emit }) => *emit / emit.x.max(emit.y).max(emit.z).max(1.0),
+        }
+    }
+
+    /// Identifier derived from the material's kind and parameters, so equal materials share
+    /// it. Never 0, which stands for the background, and below 2^24 to be exact as an `f32`.
+    pub fn id(&self) -> u32 {
+        let (kind, color, parameter) = match self {
+            Material::Lambertian(mat) => (0, mat.albedo, 0.0),
+            Material::Metal(mat) => (1, mat.albedo, 0.0),
+            Material::Glossy(mat) => (2, mat.albedo, mat.exponent),
+            Material::Isotropic(mat) => (3, mat.albedo, 0.0),
+            Material::HenyeyGreenstein(mat) => (4, mat.albedo, mat.g),
+            Material::DiffuseLight(mat) => (5, mat.emit, 0.0),
+        };
+        let values = [kind, color.x.to_bits(), color.y.to_bits(), color.z.to_bits(), parameter.to_bits()].map(u64::from);
+        (hash_seed(&values) % 0xff_ffff) as u32 + 1
+    }
+
+    /// Density with which `scatter` would have picked `direction`, needed to weight light
+    /// samples against BSDF samples.
+    pub fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f32 {
+        match self {
+            Material::Lambertian(mat) => mat.pdf(rec, direction),
+            Material::Glossy(mat) => mat.pdf(r_in, rec, direction),
+            Material::Isotropic(mat) => mat.pdf(),
+            Material::HenyeyGreenstein(mat) => mat.pdf(r_in, direction),
+            Material::Metal(_) | Material::DiffuseLight(_) => 0.0,
         }
     }
 }
@@ -43,9 +146,10 @@ impl Lambertian {
         Self { albedo }
     }
 
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        // Create a scattered ray in a random direction around the normal
-        let mut scatter_direction: Vec3 = rec.normal + Vec3::random_unit_vector();
+    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord, sampler: &mut dyn Sampler) -> bool {
+        // Create a scattered ray in a random direction around the normal,
+        // this is cosine distributed so the weight is just the albedo
+        let mut scatter_direction: Vec3 = rec.normal + sample_uniform_sphere(sampler.get_2d());
 
         if scatter_direction.near_zero() {
             // If the scatter direction is near zero, use the normal as the direction
@@ -53,10 +157,21 @@ impl Lambertian {
             scatter_direction = rec.normal;
         }
 
-        *scattered = Ray::new(&rec.point, &scatter_direction);
-        *attenuation = self.albedo;
+        srec.scattered = Ray::new(&rec.point, &scatter_direction);
+        srec.attenuation = self.albedo;
+        srec.pdf = self.pdf(rec, &scatter_direction);
+        srec.is_specular = false;
         true
     }
+
+    pub fn eval(&self, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo * self.pdf(rec, direction)
+    }
+
+    pub fn pdf(&self, rec: &HitRecord, direction: &Vec3) -> f32 {
+        let cosine = Vec3::dot(&rec.normal, &Vec3::normalize(direction));
+        (cosine / PI).max(0.0)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -71,12 +186,235 @@ impl Metal {
         Self { albedo }
     }
 
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
+    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let reflected = r_in.direction().reflect(&rec.normal);
-        *scattered = Ray::new(&rec.point, &reflected);
-        *attenuation = self.albedo;
+        srec.scattered = Ray::new(&rec.point, &reflected);
+        srec.attenuation = self.albedo;
+        srec.pdf = 0.0;
+        srec.is_specular = true;
         true
     }
 }
 
+/// Glossy reflection using a normalized Phong lobe around the mirror direction. Higher
+/// exponents give sharper reflections, at around 1000 it is close to `Metal`.
+#[derive(Copy, Clone)]
+pub struct Glossy {
+    pub albedo: Color,
+    pub exponent: f32,
+}
+
+impl Glossy {
+    pub fn new(albedo: Color, exponent: f32) -> Self {
+        Self { albedo, exponent: exponent.max(0.0) }
+    }
+
+    fn mirror(r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        Vec3::normalize(&r_in.direction()).reflect(&rec.normal)
+    }
+
+    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord, sampler: &mut dyn Sampler) -> bool {
+        // Sample the lobe around the mirror direction
+        let (u1, u2) = sampler.get_2d();
+        let cos_alpha = u1.powf(1.0 / (self.exponent + 1.0));
+        let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let frame = Onb::new(&Self::mirror(r_in, rec));
+        let direction = frame.transform(&Vec3::build(sin_alpha * phi.cos(), sin_alpha * phi.sin(), cos_alpha));
+
+        // Parts of the lobe below the surface are absorbed
+        let cos_theta = Vec3::dot(&direction, &rec.normal);
+        if cos_theta <= 0.0 {
+            return false;
+        }
 
+        srec.scattered = Ray::new(&rec.point, &direction);
+        srec.attenuation = self.albedo * cos_theta;
+        srec.pdf = self.pdf(r_in, rec, &direction);
+        srec.is_specular = false;
+        true
+    }
+
+    pub fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cos_theta = Vec3::dot(&rec.normal, &Vec3::normalize(direction));
+        if cos_theta <= 0.0 {
+            return Color::new();
+        }
+        self.albedo * (self.pdf(r_in, rec, direction) * cos_theta)
+    }
+
+    pub fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f32 {
+        let cos_alpha = Vec3::dot(&Self::mirror(r_in, rec), &Vec3::normalize(direction));
+        if cos_alpha <= 0.0 {
+            return 0.0;
+        }
+        (self.exponent + 1.0) / (2.0 * PI) * cos_alpha.powf(self.exponent)
+    }
+}
+
+/// Isotropic phase function for participating media, scatters uniformly over the whole sphere.
+#[derive(Copy, Clone)]
+pub struct Isotropic {
+    pub albedo: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+
+    pub fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord, sampler: &mut dyn Sampler) -> bool {
+        srec.scattered = Ray::new(&rec.point, &sample_uniform_sphere(sampler.get_2d()));
+        srec.attenuation = self.albedo;
+        srec.pdf = self.pdf();
+        srec.is_specular = false;
+        true
+    }
+
+    pub fn eval(&self) -> Color {
+        self.albedo * self.pdf()
+    }
+
+    pub fn pdf(&self) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Henyey-Greenstein phase function for participating media. The asymmetry `g` in (-1, 1)
+/// controls the lobe: positive values scatter forward (haze, clouds), negative backward,
+/// and 0.0 is isotropic.
+#[derive(Copy, Clone)]
+pub struct HenyeyGreenstein {
+    pub albedo: Color,
+    pub g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f32) -> Self {
+        Self { albedo, g: g.clamp(-0.999, 0.999) }
+    }
+
+    /// Value of the phase function for the cosine between the incoming propagation direction
+    /// and the scattered direction.
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord, sampler: &mut dyn Sampler) -> bool {
+        let g = self.g;
+        // Invert the CDF of the phase function to get the cosine to the incoming direction
+        let (xi, u2) = sampler.get_2d();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            (1.0 + g * g - sq * sq) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let frame = Onb::new(&r_in.direction());
+        let direction = frame.transform(&Vec3::build(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+
+        // Sampling is exact so the phase function cancels out of the weight
+        srec.scattered = Ray::new(&rec.point, &direction);
+        srec.attenuation = self.albedo;
+        srec.pdf = self.phase(cos_theta);
+        srec.is_specular = false;
+        true
+    }
+
+    pub fn eval(&self, r_in: &Ray, direction: &Vec3) -> Color {
+        self.albedo * self.pdf(r_in, direction)
+    }
+
+    pub fn pdf(&self, r_in: &Ray, direction: &Vec3) -> f32 {
+        let cos_theta = Vec3::dot(&Vec3::normalize(&r_in.direction()), &Vec3::normalize(direction));
+        self.phase(cos_theta)
+    }
+}
+
+/// Emissive material for area lights. It only emits from the front face, so quads have to
+/// be oriented towards the scene.
+#[derive(Copy, Clone)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+
+    pub fn emitted(&self, rec: &HitRecord) -> Color {
+        if !rec.front_face {
+            return Color::new();
+        }
+        self.emit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SobolSampler;
+
+    #[test]
+    fn test_henyey_greenstein_is_normalized() {
+        for g in [-0.7, 0.0, 0.3, 0.9] {
+            let hg = HenyeyGreenstein::new(Color::build(1.0, 1.0, 1.0), g);
+            // Midpoint rule over cos theta, the azimuth contributes 2 pi
+            let n = 20000;
+            let integral: f32 = (0..n)
+                .map(|i| hg.phase(-1.0 + 2.0 * (i as f32 + 0.5) / n as f32) * 2.0 * PI * 2.0 / n as f32)
+                .sum();
+            assert!((integral - 1.0).abs() < 1e-3, "g = {}: {}", g, integral);
+        }
+
+        let isotropic = Isotropic::new(Color::build(1.0, 1.0, 1.0));
+        let hg = HenyeyGreenstein::new(Color::build(1.0, 1.0, 1.0), 0.0);
+        for cos_theta in [-1.0, -0.2, 0.5, 1.0] {
+            assert!((hg.phase(cos_theta) - isotropic.pdf()).abs() < 1e-7);
+        }
+    }
+
+    #[test]
+    fn test_henyey_greenstein_mean_cosine_is_g() {
+        let r_in = Ray::new(&Vec3::new(), &Vec3::build(0.3, -1.0, 0.2));
+        let forward = Vec3::normalize(&r_in.direction());
+        let rec = HitRecord::new();
+        for g in [-0.5, 0.0, 0.8] {
+            let hg = HenyeyGreenstein::new(Color::build(1.0, 1.0, 1.0), g);
+            let mut sampler = SobolSampler::new(0);
+            let mut sum = 0.0;
+            for i in 0..4096 {
+                sampler.start_pixel_sample(0, 0, i);
+                let mut srec = ScatterRecord::new();
+                assert!(hg.scatter(&r_in, &rec, &mut srec, &mut sampler));
+                let direction = Vec3::normalize(&srec.scattered.direction());
+                // The sampled density is the one the phase function reports
+                assert!((srec.pdf - hg.pdf(&r_in, &direction)).abs() <= 1e-3 * srec.pdf);
+                sum += Vec3::dot(&forward, &direction);
+            }
+            assert!((sum / 4096.0 - g).abs() < 0.01, "g = {}: {}", g, sum / 4096.0);
+        }
+    }
+
+    #[test]
+    fn test_diffuse_light_emits_from_the_front_only() {
+        let light = Material::DiffuseLight(DiffuseLight::new(Color::build(4.0, 2.0, 1.0)));
+        let mut rec = HitRecord::new();
+        rec.front_face = true;
+        assert_eq!(light.emitted(&rec), Color::build(4.0, 2.0, 1.0));
+        rec.front_face = false;
+        assert_eq!(light.emitted(&rec), Color::new());
+
+        // Lights end paths and aren't lit themselves
+        let r_in = Ray::new(&Vec3::new(), &Vec3::build(0.0, 0.0, -1.0));
+        let mut sampler = SobolSampler::new(0);
+        assert!(!light.scatter(&r_in, &rec, &mut ScatterRecord::new(), &mut sampler));
+        assert_eq!(light.eval(&r_in, &rec, &Vec3::build(0.0, 1.0, 0.0)), Color::new());
+    }
+}
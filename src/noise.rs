@@ -0,0 +1,55 @@
+use crate::vec3::Point3;
+
+/// Integer lattice hash mapped to [0, 1).
+fn lattice(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    let mut h = seed
+        ^ (x as u32).wrapping_mul(0x8da6b343)
+        ^ (y as u32).wrapping_mul(0xd8163841)
+        ^ (z as u32).wrapping_mul(0xcb1ab31f);
+    h = (h ^ (h >> 16)).wrapping_mul(0x7feb352d);
+    h = (h ^ (h >> 15)).wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    (h >> 8) as f32 / (1u32 << 24) as f32
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Smoothly interpolated value noise in [0, 1).
+pub fn value_noise(p: &Point3, seed: u32) -> f32 {
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (fx as i32, fy as i32, fz as i32);
+    let (tx, ty, tz) = (smoothstep(p.x - fx), smoothstep(p.y - fy), smoothstep(p.z - fz));
+
+    let c = |dx: i32, dy: i32, dz: i32| lattice(x + dx, y + dy, z + dz, seed);
+
+    let x00 = lerp(c(0, 0, 0), c(1, 0, 0), tx);
+    let x10 = lerp(c(0, 1, 0), c(1, 1, 0), tx);
+    let x01 = lerp(c(0, 0, 1), c(1, 0, 1), tx);
+    let x11 = lerp(c(0, 1, 1), c(1, 1, 1), tx);
+
+    lerp(lerp(x00, x10, ty), lerp(x01, x11, ty), tz)
+}
+
+/// Fractal Brownian motion, a sum of `octaves` layers of value noise each with double the
+/// frequency and half the amplitude of the last. Normalized to [0, 1).
+pub fn fbm(p: &Point3, octaves: u32, seed: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut total = 0.0;
+    let mut q = *p;
+
+    for octave in 0..octaves.max(1) {
+        sum += amplitude * value_noise(&q, seed.wrapping_add(octave));
+        total += amplitude;
+        amplitude *= 0.5;
+        q *= 2.0;
+    }
+
+    sum / total
+}
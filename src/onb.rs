@@ -0,0 +1,67 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis built around a single direction `w`, used to turn directions sampled
+/// around the z axis into directions around a normal (or any other axis).
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: &Vec3) -> Self {
+        let w = Vec3::normalize(w);
+        // Pick a helper axis that is not parallel to w
+        let a = if w.x.abs() > 0.9 { Vec3::build(0.0, 1.0, 0.0) } else { Vec3::build(1.0, 0.0, 0.0) };
+        let v = Vec3::normalize(&Vec3::cross(&w, &a));
+        let u = Vec3::cross(&v, &w);
+        Self { u, v, w }
+    }
+
+    /// Transforms a vector given in local (u, v, w) coordinates into world space.
+    pub fn transform(&self, local: &Vec3) -> Vec3 {
+        self.u * local.x + self.v * local.y + self.w * local.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_axis_aligned_frames_are_orthonormal() {
+        for axis in [
+            Vec3::build(1.0, 0.0, 0.0),
+            Vec3::build(-1.0, 0.0, 0.0),
+            Vec3::build(0.0, 1.0, 0.0),
+            Vec3::build(0.0, -1.0, 0.0),
+            Vec3::build(0.0, 0.0, 1.0),
+            Vec3::build(0.0, 0.0, -3.0),
+        ] {
+            let onb = Onb::new(&axis);
+            for v in [onb.u, onb.v, onb.w] {
+                assert!((v.length() - 1.0).abs() < 1e-6, "{:?}", axis);
+            }
+            assert!(Vec3::dot(&onb.u, &onb.v).abs() < 1e-6);
+            assert!(Vec3::dot(&onb.v, &onb.w).abs() < 1e-6);
+            assert!(Vec3::dot(&onb.w, &onb.u).abs() < 1e-6);
+            assert_eq!(onb.w, Vec3::normalize(&axis));
+            // Local z maps onto the axis
+            assert!((onb.transform(&Vec3::build(0.0, 0.0, 1.0)) - onb.w).length() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_frames_are_right_handed() {
+        for axis in [
+            Vec3::build(1.0, 0.0, 0.0),
+            Vec3::build(0.0, -1.0, 0.0),
+            Vec3::build(0.0, 0.0, 1.0),
+            Vec3::build(0.3, -0.5, 0.8),
+            Vec3::build(-0.95, 0.2, 0.1),
+        ] {
+            let onb = Onb::new(&axis);
+            assert!((Vec3::cross(&onb.u, &onb.v) - onb.w).length() < 1e-6, "{:?}", axis);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::f32::consts::PI;
+
+use crate::hittable::Hittable;
+use crate::onb::Onb;
+use crate::vec3::{Point3, Vec3};
+use crate::sampler::{sample_uniform_sphere, Sampler};
+
+/// A probability density over directions that can also be sampled.
+pub trait Pdf {
+    /// Solid angle density of `direction`.
+    fn value(&self, direction: &Vec3) -> f32;
+
+    /// Draws a direction distributed according to `value`.
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
+}
+
+/// Uniform over the whole sphere of directions.
+#[allow(dead_code)]
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        sample_uniform_sphere(sampler.get_2d())
+    }
+}
+
+/// Cosine weighted hemisphere around a normal, the ideal density for Lambertian surfaces.
+#[allow(dead_code)]
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+#[allow(dead_code)]
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        Self { uvw: Onb::new(normal) }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let cosine = Vec3::dot(&Vec3::normalize(direction), &self.uvw.w);
+        (cosine / PI).max(0.0)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.get_2d();
+        let phi = 2.0 * PI * r1;
+        let local = Vec3::build(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), (1.0 - r2).sqrt());
+        self.uvw.transform(&local)
+    }
+}
+
+/// Directions towards a hittable (usually the scene's light list) as seen from `origin`.
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: &Point3) -> Self {
+        Self { objects, origin: *origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.objects.random(&self.origin, sampler)
+    }
+}
+
+/// Picks one of two densities, the first with probability `weight`. Its value is the
+/// weighted average of both, so it can be used anywhere either strategy could.
+pub struct MixturePdf<'a> {
+    a: &'a dyn Pdf,
+    b: &'a dyn Pdf,
+    weight: f32,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(a: &'a dyn Pdf, b: &'a dyn Pdf) -> Self {
+        Self::with_weight(a, b, 0.5)
+    }
+
+    pub fn with_weight(a: &'a dyn Pdf, b: &'a dyn Pdf, weight: f32) -> Self {
+        Self { a, b, weight: weight.clamp(0.0, 1.0) }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        self.weight * self.a.value(direction) + (1.0 - self.weight) * self.b.value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() < self.weight {
+            self.a.generate(sampler)
+        } else {
+            self.b.generate(sampler)
+        }
+    }
+}
+
+/// Heuristics for weighting two sampling strategies against each other when both could have
+/// produced the same direction.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MisHeuristic {
+    Balance,
+    Power,
+}
+
+impl MisHeuristic {
+    /// Weight for a sample drawn from the strategy with density `pdf`, given that the other
+    /// strategy would have produced it with density `other_pdf`.
+    pub fn weight(&self, pdf: f32, other_pdf: f32) -> f32 {
+        match self {
+            MisHeuristic::Balance => balance_heuristic(pdf, other_pdf),
+            MisHeuristic::Power => power_heuristic(pdf, other_pdf),
+        }
+    }
+}
+
+pub fn balance_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    if pdf + other_pdf <= 0.0 {
+        return 0.0;
+    }
+    pdf / (pdf + other_pdf)
+}
+
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b <= 0.0 || !a.is_finite() {
+        // An infinite density only happens for delta distributions, which always win
+        return if pdf > 0.0 { 1.0 } else { 0.0 };
+    }
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SobolSampler;
+
+    #[test]
+    fn test_heuristics_sum_to_one() {
+        for (a, b) in [(0.3, 2.0), (1.0, 1.0), (5.0, 0.01)] {
+            assert!((balance_heuristic(a, b) + balance_heuristic(b, a) - 1.0).abs() < 1e-6);
+            assert!((power_heuristic(a, b) + power_heuristic(b, a) - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(power_heuristic(1.0, 0.0), 1.0);
+        assert_eq!(balance_heuristic(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_cosine_pdf_samples_upper_hemisphere() {
+        let normal = Vec3::build(0.0, 1.0, 0.0);
+        let pdf = CosinePdf::new(&normal);
+        let mut sampler = SobolSampler::new(0);
+        for i in 0..100 {
+            sampler.start_pixel_sample(0, 0, i);
+            let d = pdf.generate(&mut sampler);
+            assert!(Vec3::dot(&d, &normal) >= 0.0);
+            assert!(pdf.value(&d) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_mixture_value_is_weighted_average() {
+        let normal = Vec3::build(0.0, 0.0, 1.0);
+        let cosine = CosinePdf::new(&normal);
+        let mixture = MixturePdf::with_weight(&cosine, &SpherePdf, 0.25);
+        let d = Vec3::build(0.0, 0.0, 1.0);
+        let expected = 0.25 / PI + 0.75 / (4.0 * PI);
+        assert!((mixture.value(&d) - expected).abs() < 1e-6);
+    }
+}
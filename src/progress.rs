@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::framebuffer::Framebuffer;
+use crate::stats::RenderStats;
+use crate::tile::Tile;
+
+/// Summary of a finished render, passed to `RenderObserver::completed`.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderSummary {
+    pub width: u32,
+    pub height: u32,
+    /// Number of passes over the image, 1 for a plain render.
+    pub passes: u32,
+    pub total_samples: u64,
+    pub elapsed: Duration,
+    pub stats: RenderStats,
+}
+
+impl RenderSummary {
+    pub fn samples_per_pixel(&self) -> f32 {
+        self.total_samples as f32 / (self.width * self.height).max(1) as f32
+    }
+}
+
+/// Receives events while `Camera::render_with` runs, so a front end can show progress and the
+/// image as it builds up. All methods do nothing by default.
+pub trait RenderObserver {
+    fn started(&mut self, _width: u32, _height: u32) {}
+
+    /// A tile has received its samples for the current pass, `framebuffer` holds the image so far.
+    fn tile_finished(&mut self, _tile: &Tile, _framebuffer: &Framebuffer) {}
+
+    /// Another pass over the image is done, `pass` counts the finished passes.
+    fn pass_finished(&mut self, _pass: u32, _framebuffer: &Framebuffer) {}
+
+    /// The render was stopped through its cancellation flag, `completed` won't be called.
+    fn cancelled(&mut self, _framebuffer: &Framebuffer) {}
+
+    fn completed(&mut self, _framebuffer: &Framebuffer, _summary: &RenderSummary) {}
+}
+
+/// Observer ignoring every event, for renders embedded where nobody watches the progress.
+#[allow(dead_code)]
+pub struct Silent;
+
+impl RenderObserver for Silent {}
+
+/// Progress bar on the terminal, filling up during the first pass and showing the noise of
+/// the image after every further pass.
+pub struct ConsoleProgress {
+    pb: ProgressBar,
+}
+
+impl ConsoleProgress {
+    pub fn new() -> Self {
+        Self { pb: ProgressBar::hidden() }
+    }
+}
+
+impl Default for ConsoleProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderObserver for ConsoleProgress {
+    fn started(&mut self, width: u32, height: u32) {
+        self.pb = ProgressBar::new(u64::from(width * height));
+        self.pb.set_style(ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} ({eta}) {msg}")
+            .unwrap()
+            .progress_chars("#>-"));
+    }
+
+    fn tile_finished(&mut self, tile: &Tile, _framebuffer: &Framebuffer) {
+        // Later passes revisit tiles, they only update the message
+        if self.pb.position() < self.pb.length().unwrap_or(0) {
+            self.pb.inc(u64::from(tile.pixel_count()));
+        }
+    }
+
+    fn pass_finished(&mut self, pass: u32, framebuffer: &Framebuffer) {
+        self.pb.set_position(self.pb.length().unwrap_or(0));
+        self.pb.set_message(format!("pass {}, {} samples per pixel, error {:.4}", pass, framebuffer.max_samples(), framebuffer.mean_relative_error()));
+    }
+
+    fn cancelled(&mut self, _framebuffer: &Framebuffer) {
+        self.pb.abandon_with_message("Cancelled");
+    }
+
+    fn completed(&mut self, _framebuffer: &Framebuffer, summary: &RenderSummary) {
+        self.pb.finish_with_message("Done!");
+        if summary.passes > 1 {
+            println!("{:.1} samples per pixel on average in {} passes, {:.1?}", summary.samples_per_pixel(), summary.passes, summary.elapsed);
+        } else {
+            println!("{:.1} samples per pixel on average in {:.1?}", summary.samples_per_pixel(), summary.elapsed);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::f32::consts::PI;
+
+use crate::vec3::Vec3;
+use raytracing::degrees_to_radians;
+
+/// How positions on the film map to camera rays. Rays are given in camera space, with x to
+/// the right, y up and the camera looking down -z.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Projection {
+    /// Pinhole camera with a vertical field of view in degrees.
+    Perspective { vfov: f32 },
+    /// Parallel rays from a film `height` world units high, for technical and isometric views
+    /// without perspective foreshortening.
+    Orthographic { height: f32 },
+    /// Fisheye whose image circle, inscribed in the shorter side of the image, covers `fov`
+    /// degrees. The distance from the image center is proportional to the angle to the
+    /// viewing direction.
+    FisheyeEquidistant { fov: f32 },
+    /// Fisheye like `FisheyeEquidistant`, but every area of the image covers a solid angle
+    /// proportional to its size, which compresses the rim more.
+    FisheyeEquisolid { fov: f32 },
+    /// Full 360° by 180° latitude-longitude panorama, in the layout `EnvironmentMap` reads,
+    /// with the viewing direction in the center of the image.
+    Equirectangular,
+}
+
+impl Projection {
+    /// Ray through the film position `x`, `y` in pixels of a `width` by `height` image, as
+    /// its origin relative to the camera and its direction. `None` outside of a fisheye's
+    /// image circle.
+    pub fn ray(&self, x: f32, y: f32, width: u32, height: u32) -> Option<(Vec3, Vec3)> {
+        let (width, height) = (width as f32, height as f32);
+        let aspect_ratio = width / height;
+        // Film position in [-1, 1] with y pointing up
+        let sx = 2.0 * x / width - 1.0;
+        let sy = 1.0 - 2.0 * y / height;
+
+        match *self {
+            Projection::Perspective { vfov } => {
+                let h = (degrees_to_radians(vfov) / 2.0).tan();
+                Some((Vec3::new(), Vec3::build(sx * h * aspect_ratio, sy * h, -1.0)))
+            }
+            Projection::Orthographic { height: film_height } => {
+                let offset = Vec3::build(sx * aspect_ratio, sy, 0.0) * (film_height / 2.0);
+                Some((offset, Vec3::build(0.0, 0.0, -1.0)))
+            }
+            Projection::FisheyeEquidistant { fov } | Projection::FisheyeEquisolid { fov } => {
+                // Distance from the center relative to the radius of the image circle
+                let (px, py) = (sx * width, sy * height);
+                let r = (px * px + py * py).sqrt() / width.min(height);
+                if r > 1.0 {
+                    return None;
+                }
+                let max_theta = degrees_to_radians(fov.min(360.0)) / 2.0;
+                let theta = match self {
+                    Projection::FisheyeEquidistant { .. } => r * max_theta,
+                    _ => 2.0 * (r * (max_theta / 2.0).sin()).clamp(-1.0, 1.0).asin(),
+                };
+                let phi = py.atan2(px);
+                Some((Vec3::new(), Vec3::build(theta.sin() * phi.cos(), theta.sin() * phi.sin(), -theta.cos())))
+            }
+            Projection::Equirectangular => {
+                let phi = (x / width - 0.5) * 2.0 * PI;
+                let theta = y / height * PI;
+                Some((Vec3::new(), Vec3::build(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())))
+            }
+        }
+    }
+
+    /// Film position in pixels of a `width` by `height` image the camera space `point` is seen
+    /// at, the inverse of `ray`. `None` behind a perspective camera and outside of a fisheye's
+    /// image circle.
+    pub fn project(&self, point: &Vec3, width: u32, height: u32) -> Option<(f32, f32)> {
+        let (width, height) = (width as f32, height as f32);
+        let aspect_ratio = width / height;
+        let film = |sx: f32, sy: f32| ((sx + 1.0) * width / 2.0, (1.0 - sy) * height / 2.0);
+
+        match *self {
+            Projection::Perspective { vfov } => {
+                if point.z >= 0.0 {
+                    return None;
+                }
+                let h = (degrees_to_radians(vfov) / 2.0).tan();
+                Some(film(point.x / (-point.z * h * aspect_ratio), point.y / (-point.z * h)))
+            }
+            Projection::Orthographic { height: film_height } => {
+                Some(film(point.x / (film_height / 2.0 * aspect_ratio), point.y / (film_height / 2.0)))
+            }
+            Projection::FisheyeEquidistant { fov } | Projection::FisheyeEquisolid { fov } => {
+                let direction = Vec3::normalize(point);
+                let theta = (-direction.z).clamp(-1.0, 1.0).acos();
+                let max_theta = degrees_to_radians(fov.min(360.0)) / 2.0;
+                let r = match self {
+                    Projection::FisheyeEquidistant { .. } => theta / max_theta,
+                    _ => (theta / 2.0).sin() / (max_theta / 2.0).sin(),
+                };
+                if r > 1.0 {
+                    return None;
+                }
+                let phi = direction.y.atan2(direction.x);
+                let radius = r * width.min(height);
+                Some(film(radius * phi.cos() / width, radius * phi.sin() / height))
+            }
+            Projection::Equirectangular => {
+                let direction = Vec3::normalize(point);
+                let phi = direction.x.atan2(-direction.z);
+                let theta = direction.y.clamp(-1.0, 1.0).acos();
+                Some(((phi / (2.0 * PI) + 0.5) * width, theta / PI * height))
+            }
+        }
+    }
+
+    /// Whether every ray looks in roughly the same direction, so distances are best measured
+    /// along the viewing direction rather than from the camera.
+    pub fn is_planar(&self) -> bool {
+        matches!(self, Projection::Perspective { .. } | Projection::Orthographic { .. })
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective { vfov: 90.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direction(projection: Projection, x: f32, y: f32, width: u32, height: u32) -> Vec3 {
+        Vec3::normalize(&projection.ray(x, y, width, height).unwrap().1)
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn test_center_looks_forward() {
+        let forward = Vec3::build(0.0, 0.0, -1.0);
+        for projection in [
+            Projection::default(),
+            Projection::Orthographic { height: 2.0 },
+            Projection::FisheyeEquidistant { fov: 180.0 },
+            Projection::FisheyeEquisolid { fov: 180.0 },
+            Projection::Equirectangular,
+        ] {
+            assert!(close(direction(projection, 50.0, 25.0, 100, 50), forward), "{:?}", projection);
+        }
+    }
+
+    #[test]
+    fn test_fisheye_rim_reaches_half_the_field_of_view() {
+        for projection in [Projection::FisheyeEquidistant { fov: 180.0 }, Projection::FisheyeEquisolid { fov: 180.0 }] {
+            // Right edge of the image circle in a square image looks sideways
+            assert!(close(direction(projection, 100.0, 50.0, 100, 100), Vec3::build(1.0, 0.0, 0.0)));
+            assert!(close(direction(projection, 50.0, 0.0, 100, 100), Vec3::build(0.0, 1.0, 0.0)));
+            assert!(projection.ray(0.0, 0.0, 100, 100).is_none());
+        }
+        // Halfway out, the equisolid fisheye hasn't turned as far off axis yet
+        let angle = |projection| direction(projection, 75.0, 50.0, 100, 100).x.asin().to_degrees();
+        assert!((angle(Projection::FisheyeEquidistant { fov: 180.0 }) - 45.0).abs() < 1e-3);
+        assert!(angle(Projection::FisheyeEquisolid { fov: 180.0 }) < 45.0);
+    }
+
+    #[test]
+    fn test_equirectangular_covers_the_sphere() {
+        let projection = Projection::Equirectangular;
+        assert!(close(direction(projection, 0.0, 50.0, 200, 100), Vec3::build(0.0, 0.0, 1.0)));
+        assert!(close(direction(projection, 150.0, 50.0, 200, 100), Vec3::build(1.0, 0.0, 0.0)));
+        assert!(close(direction(projection, 50.0, 0.0, 200, 100), Vec3::build(0.0, 1.0, 0.0)));
+        assert!(close(direction(projection, 50.0, 100.0, 200, 100), Vec3::build(0.0, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_project_inverts_ray() {
+        for projection in [
+            Projection::default(),
+            Projection::Orthographic { height: 2.0 },
+            Projection::FisheyeEquidistant { fov: 180.0 },
+            Projection::FisheyeEquisolid { fov: 220.0 },
+            Projection::Equirectangular,
+        ] {
+            // The corners are outside a fisheye's image circle
+            for (x, y) in [(50.0, 25.0), (20.0, 10.0), (80.0, 40.0), (45.0, 30.0), (65.0, 15.0)] {
+                let (origin, direction) = match projection.ray(x, y, 100, 50) {
+                    Some(ray) => ray,
+                    None => continue,
+                };
+                let (px, py) = projection.project(&(origin + direction * 3.0), 100, 50).unwrap();
+                assert!((px - x).abs() < 1e-3 && (py - y).abs() < 1e-3, "{:?} ({}, {}) -> ({}, {})", projection, x, y, px, py);
+            }
+        }
+        assert!(Projection::default().project(&Vec3::build(0.0, 0.0, 1.0), 100, 50).is_none());
+    }
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let projection = Projection::Orthographic { height: 4.0 };
+        let (corner, direction) = projection.ray(0.0, 0.0, 200, 100).unwrap();
+        assert!(close(corner, Vec3::build(-4.0, 2.0, 0.0)));
+        assert!(close(direction, projection.ray(130.0, 20.0, 200, 100).unwrap().1));
+    }
+}
@@ -0,0 +1,218 @@
+use std::rc::Rc;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+use crate::sampler::Sampler;
+use crate::stats::{self, Counter};
+use raytracing::rng::Rng;
+
+/// Parallelogram with corner `q` spanned by the edges `u` and `v`.
+/// The front face is on the side `cross(u, v)` points to.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f32,
+    area: f32,
+    pub material: Material,
+}
+
+impl Quad {
+    pub fn new(q: &Point3, u: &Vec3, v: &Vec3, material: Material) -> Self {
+        let n = Vec3::cross(u, v);
+        let normal = Vec3::normalize(&n);
+
+        Self {
+            q: *q,
+            u: *u,
+            v: *v,
+            // Used to find the planar coordinates of a hit point in terms of u and v
+            w: n / Vec3::dot(&n, &n),
+            normal,
+            d: Vec3::dot(&normal, q),
+            area: n.length(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord, _rng: &mut Rng) -> bool {
+        stats::count(Counter::QuadTests);
+        let denom = Vec3::dot(&self.normal, &r.direction());
+
+        // The ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - Vec3::dot(&self.normal, &r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        // Check that the hit point on the plane lies within the quad
+        let intersection = r.at(t);
+        let planar = intersection - self.q;
+        let alpha = Vec3::dot(&self.w, &Vec3::cross(&planar, &self.v));
+        let beta = Vec3::dot(&self.w, &Vec3::cross(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.point = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(r, &self.normal);
+        rec.material = self.material;
+
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0)) {
+            return 0.0;
+        }
+
+        // Convert the uniform area density to solid angle
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (Vec3::dot(direction, &self.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.get_2d();
+        let p = self.q + self.u * a + self.v * b;
+        p - *origin
+    }
+}
+
+/// Builds the six outward facing sides of the axis aligned box spanned by the corners `a` and `b`.
+pub fn make_box(a: &Point3, b: &Point3, material: Material) -> HittableList {
+    let mut sides = HittableList::new();
+
+    let min = Point3::build(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Point3::build(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = Vec3::build(max.x - min.x, 0.0, 0.0);
+    let dy = Vec3::build(0.0, max.y - min.y, 0.0);
+    let dz = Vec3::build(0.0, 0.0, max.z - min.z);
+
+    sides.add(Rc::new(Quad::new(&Point3::build(min.x, min.y, max.z), &dx, &dy, material))); // front
+    sides.add(Rc::new(Quad::new(&Point3::build(max.x, min.y, max.z), &-dz, &dy, material))); // right
+    sides.add(Rc::new(Quad::new(&Point3::build(max.x, min.y, min.z), &-dx, &dy, material))); // back
+    sides.add(Rc::new(Quad::new(&Point3::build(min.x, min.y, min.z), &dz, &dy, material))); // left
+    sides.add(Rc::new(Quad::new(&Point3::build(min.x, max.y, max.z), &dx, &-dz, material))); // top
+    sides.add(Rc::new(Quad::new(&Point3::build(min.x, min.y, min.z), &dx, &dz, material))); // bottom
+
+    sides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SobolSampler;
+
+    fn unit_square() -> Quad {
+        // Unit square in the z = 0 plane, facing +z
+        Quad::new(&Point3::new(), &Vec3::build(1.0, 0.0, 0.0), &Vec3::build(0.0, 1.0, 0.0), Material::default())
+    }
+
+    fn hit(object: &dyn Hittable, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        object
+            .hit(&Ray::new(&origin, &direction), &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0))
+            .then_some(rec)
+    }
+
+    #[test]
+    fn test_quad_hits() {
+        let quad = unit_square();
+        let down = Vec3::build(0.0, 0.0, -1.0);
+        let rec = hit(&quad, Point3::build(0.25, 0.75, 2.0), down).unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert_eq!((rec.u, rec.v), (0.25, 0.75));
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::build(0.0, 0.0, 1.0));
+
+        // From behind the normal is flipped towards the ray
+        let rec = hit(&quad, Point3::build(0.5, 0.5, -1.0), -down).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::build(0.0, 0.0, -1.0));
+
+        // Edges and corners belong to the quad
+        assert!(hit(&quad, Point3::build(0.0, 0.5, 1.0), down).is_some());
+        assert!(hit(&quad, Point3::build(1.0, 1.0, 1.0), down).is_some());
+
+        assert!(hit(&quad, Point3::build(1.01, 0.5, 1.0), down).is_none());
+        assert!(hit(&quad, Point3::build(0.5, -0.01, 1.0), down).is_none());
+        // Parallel to the plane, even when running inside it
+        assert!(hit(&quad, Point3::build(-1.0, 0.5, 1.0), Vec3::build(1.0, 0.0, 0.0)).is_none());
+        assert!(hit(&quad, Point3::build(-1.0, 0.5, 0.0), Vec3::build(1.0, 0.0, 0.0)).is_none());
+        // Behind the ray's origin
+        assert!(hit(&quad, Point3::build(0.5, 0.5, 1.0), -down).is_none());
+    }
+
+    #[test]
+    fn test_quad_pdf_matches_solid_angle() {
+        let mut sampler = SobolSampler::new(0);
+
+        // Small and far away, the solid angle is close to area * cosine / distance^2
+        let quad = Quad::new(&Point3::build(-0.05, -0.05, -10.0), &Vec3::build(0.1, 0.0, 0.0), &Vec3::build(0.0, 0.1, 0.0), Material::default());
+        for i in 0..64 {
+            sampler.start_pixel_sample(0, 0, i);
+            let direction = quad.random(&Point3::new(), &mut sampler);
+            let pdf = quad.pdf_value(&Point3::new(), &direction);
+            assert!((pdf * 0.01 / 100.0 - 1.0).abs() < 1e-3, "{}", pdf);
+        }
+
+        // Averaging 1 / pdf over the sampled directions gives the solid angle, here the
+        // exact one of a rectangle seen from a point on its axis
+        let (a, b, d) = (1.0f32, 0.5f32, 0.8f32);
+        let quad = Quad::new(&Point3::build(-a, -b, -d), &Vec3::build(2.0 * a, 0.0, 0.0), &Vec3::build(0.0, 2.0 * b, 0.0), Material::default());
+        let exact = 4.0 * (a * b / ((a * a + d * d) * (b * b + d * d)).sqrt()).asin();
+        let mut sum = 0.0;
+        for i in 0..1024 {
+            sampler.start_pixel_sample(0, 0, i);
+            let direction = quad.random(&Point3::new(), &mut sampler);
+            let pdf = quad.pdf_value(&Point3::new(), &direction);
+            assert!(pdf > 0.0);
+            sum += 1.0 / pdf;
+        }
+        assert!((sum / 1024.0 - exact).abs() < 0.01 * exact, "{} {}", sum / 1024.0, exact);
+
+        // Directions missing the quad can't have been sampled
+        assert_eq!(quad.pdf_value(&Point3::new(), &Vec3::build(0.0, 0.0, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn test_box_is_closed_and_faces_outwards() {
+        let cube = make_box(&Point3::build(1.0, 1.0, 1.0), &Point3::build(-1.0, -1.0, -1.0), Material::default());
+        assert_eq!(cube.objects.len(), 6);
+
+        let mut sampler = SobolSampler::new(0);
+        for i in 0..256 {
+            sampler.start_pixel_sample(0, 0, i);
+            let direction = crate::sampler::sample_uniform_sphere(sampler.get_2d());
+            // From inside every direction leaves through exactly one side, seen from behind
+            let sides = cube.objects.iter().filter_map(|side| hit(side.as_ref(), Point3::new(), direction)).collect::<Vec<_>>();
+            assert_eq!(sides.len(), 1, "{:?}", direction);
+            assert!(!sides[0].front_face);
+            let exit = sides[0].point;
+            assert!((exit.x.abs().max(exit.y.abs()).max(exit.z.abs()) - 1.0).abs() < 1e-5);
+
+            // From outside the ray enters through a front face
+            let rec = hit(&cube, direction * 3.0, -direction).unwrap();
+            assert!(rec.front_face);
+            assert!(Vec3::dot(&rec.normal, &direction) > 0.0);
+        }
+    }
+}
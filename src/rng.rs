@@ -0,0 +1,87 @@
+/// PCG32 random number generator (O'Neill, "PCG: A Family of Simple Fast Space-Efficient
+/// Statistically Good Algorithms for Random Number Generation"). Unlike `rand::random` its
+/// output is fully determined by the seed, so renders can be reproduced bit for bit.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+const MULTIPLIER: u64 = 0x5851f42d4c957f2d;
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self::with_stream(seed, 0)
+    }
+
+    /// Generators with the same seed but different streams produce unrelated sequences.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Self { state: 0, inc: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniform float in [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        // 24 random bits fill the mantissa exactly, so the result can never round up to 1
+        (self.next_u32() >> 8) as f32 * (1.0 / 16777216.0)
+    }
+}
+
+/// Finalizer of SplitMix64, turns similar inputs into unrelated outputs.
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+/// Combines several values, e.g. a render seed and pixel coordinates, into one seed.
+pub fn hash_seed(values: &[u64]) -> u64 {
+    let mut h = 0x9e3779b97f4a7c15u64;
+    for v in values {
+        h = mix_bits(h ^ *v);
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let sa: Vec<u32> = (0..8).map(|_| a.next_u32()).collect();
+        let sb: Vec<u32> = (0..8).map(|_| b.next_u32()).collect();
+        let sc: Vec<u32> = (0..8).map(|_| c.next_u32()).collect();
+        assert_eq!(sa, sb);
+        assert_ne!(sa, sc);
+    }
+
+    #[test]
+    fn test_floats_in_unit_range() {
+        let mut rng = Rng::new(7);
+        let mut sum = 0.0;
+        for _ in 0..10000 {
+            let v = rng.next_f32();
+            assert!((0.0..1.0).contains(&v));
+            sum += v;
+        }
+        assert!((sum / 10000.0 - 0.5).abs() < 0.02);
+    }
+}
@@ -0,0 +1,627 @@
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+use crate::vec3::Vec3;
+use raytracing::rng::{hash_seed, mix_bits, Rng};
+
+/// Source of the random numbers used to render one sample of a pixel. Every call to `get_1d`
+/// or `get_2d` consumes the next dimension of the sample, so samplers can make the values
+/// well distributed across the samples of a pixel instead of independent white noise.
+pub trait Sampler {
+    /// Starts sample `sample_index` of pixel (x, y), resetting the dimension counter.
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32);
+
+    /// Next value in [0, 1).
+    fn get_1d(&mut self) -> f32;
+
+    /// Next pair of values in [0, 1)^2.
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+/// The samplers the camera can be configured with.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SamplerType {
+    /// Independent uniform random numbers (white noise).
+    Independent,
+    /// Jittered grid per dimension, with the strata shuffled between dimensions.
+    Stratified,
+    /// Owen scrambled Halton sequence.
+    Halton,
+    /// Owen scrambled Sobol (0, 2) sequence, padded to higher dimensions by shuffling.
+    Sobol,
+    /// Sobol points shared by all pixels but offset by a blue noise texture, which pushes the
+    /// remaining error to high frequencies where it is far less visible.
+    BlueNoise,
+}
+
+impl SamplerType {
+    /// Creates the sampler. Renders with the same `seed` get exactly the same samples.
+    pub fn create(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, true, seed)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerType::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+        }
+    }
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Maps a point of the unit square to a uniformly distributed unit vector.
+pub fn sample_uniform_sphere(u: (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::build(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Hashes a handful of values into one, used to seed per pixel and per dimension scrambles.
+fn hash(values: &[u64]) -> u32 {
+    hash_seed(values) as u32
+}
+
+/// Generator for the random parts of one pixel sample, independent of the order pixels are
+/// rendered in.
+fn pixel_sample_rng(seed: u64, pixel: (u32, u32), sample_index: u32) -> Rng {
+    Rng::new(hash_seed(&[seed, pixel.0 as u64, pixel.1 as u64, sample_index as u64]))
+}
+
+fn u32_to_float(v: u32) -> f32 {
+    (v as f32 * (1.0 / 4294967296.0)).min(ONE_MINUS_EPSILON)
+}
+
+/// Element `i` of a random permutation of [0, l) selected by `p` (Kensler, "Correlated
+/// Multi-Jittered Sampling").
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+/// Hash based Owen scrambling of the bits of `v` (Burley, "Practical Hash-based Owen
+/// Scrambling"). Operates on reversed bits so the most significant bit is scrambled first.
+fn nested_uniform_scramble(v: u32, seed: u32) -> u32 {
+    let mut x = v.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// White noise, every pixel sample draws from its own random number generator.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: Rng::new(seed) }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.rng = pixel_sample_rng(self.seed, (x, y), sample_index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.next_f32()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.rng.next_f32(), self.rng.next_f32())
+    }
+}
+
+/// Splits [0, 1) into one stratum per sample and puts one jittered value in each.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    x_strata: u32,
+    y_strata: u32,
+    jitter: bool,
+    seed: u64,
+    rng: Rng,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    /// Splits the samples into a grid that is as square as possible. Without `jitter` the
+    /// samples are placed at the centers of the strata.
+    pub fn new(samples_per_pixel: u32, jitter: bool, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = (samples_per_pixel as f32).sqrt().floor().max(1.0) as u32;
+        let y_strata = samples_per_pixel.div_ceil(x_strata);
+        Self {
+            samples_per_pixel,
+            x_strata,
+            y_strata,
+            jitter,
+            seed,
+            rng: Rng::new(seed),
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn offset(&mut self) -> f32 {
+        if self.jitter { self.rng.next_f32() } else { 0.5 }
+    }
+
+    /// Stratum of this sample in the current dimension, the permutation decorrelates dimensions.
+    fn stratum(&self, count: u32) -> u32 {
+        let seed = hash(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, self.dimension as u64]);
+        permutation_element(self.sample_index % count, count, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = pixel_sample_rng(self.seed, self.pixel, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        self.dimension += 1;
+        ((stratum as f32 + self.offset()) / self.samples_per_pixel as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let stratum = self.stratum(self.x_strata * self.y_strata);
+        self.dimension += 2;
+        let (sx, sy) = (stratum % self.x_strata, stratum / self.x_strata);
+        (
+            ((sx as f32 + self.offset()) / self.x_strata as f32).min(ONE_MINUS_EPSILON),
+            ((sy as f32 + self.offset()) / self.y_strata as f32).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+/// Number of dimensions with their own prime base, later dimensions are random.
+const HALTON_DIMENSIONS: usize = 128;
+
+fn first_primes(count: usize) -> Vec<u32> {
+    let mut primes: Vec<u32> = Vec::with_capacity(count);
+    let mut candidate = 2;
+    while primes.len() < count {
+        if primes.iter().take_while(|&&p| p * p <= candidate).all(|&p| candidate % p != 0) {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+/// Radical inverse of `a` in `base` with every digit permuted depending on the digits below
+/// it, which is Owen scrambling for arbitrary bases.
+fn owen_scrambled_radical_inverse(base: u32, mut a: u64, seed: u32) -> f32 {
+    let base64 = base as u64;
+    let inv_base = 1.0 / base as f64;
+    let mut reversed_digits: u64 = 0;
+    let mut inv_base_m = 1.0f64;
+    // Stop when further digits no longer change the f32 result
+    while 1.0 - (base as f64 - 1.0) * inv_base_m < 1.0 && inv_base_m > 1e-9 {
+        let next = a / base64;
+        let digit = (a - next * base64) as u32;
+        let digit_hash = mix_bits(seed as u64 ^ reversed_digits) as u32;
+        let digit = permutation_element(digit, base, digit_hash) as u64;
+        reversed_digits = reversed_digits * base64 + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    ((reversed_digits as f64 * inv_base_m) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// Halton sequence with a prime base per dimension, Owen scrambled per pixel.
+pub struct HaltonSampler {
+    primes: Vec<u32>,
+    seed: u64,
+    rng: Rng,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            primes: first_primes(HALTON_DIMENSIONS),
+            seed,
+            rng: Rng::new(seed),
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn sample_dimension(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= self.primes.len() {
+            return self.rng.next_f32();
+        }
+        let seed = hash(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, dimension as u64]);
+        owen_scrambled_radical_inverse(self.primes[dimension], self.sample_index as u64, seed)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = pixel_sample_rng(self.seed, self.pixel, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.sample_dimension()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.sample_dimension(), self.sample_dimension())
+    }
+}
+
+/// The first two dimensions of the Sobol sequence as 32 bit fixed point. Together they form a
+/// (0, 2) sequence: every power of two prefix is perfectly stratified in 2D.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let x = index.reverse_bits();
+    let mut y = 0;
+    let mut v = 1u32 << 31;
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+    }
+    (x, y)
+}
+
+/// Padded Sobol: every dimension pair uses the (0, 2) sequence with its own Owen scramble and
+/// its own shuffle of the sample order, seeded per pixel.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_pair(&mut self) -> (f32, f32) {
+        let seed = hash(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, self.dimension as u64]);
+        self.dimension += 1;
+
+        let index = nested_uniform_scramble(self.sample_index, seed);
+        let (x, y) = sobol_2d(index);
+        (
+            u32_to_float(nested_uniform_scramble(x, mix_bits(seed as u64 ^ 1) as u32)),
+            u32_to_float(nested_uniform_scramble(y, mix_bits(seed as u64 ^ 2) as u32)),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.next_pair().0
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        self.next_pair()
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Generates a tileable blue noise texture of `size * size` ranks in [0, 1) using Ulichney's
+/// void and cluster method.
+pub fn blue_noise_texture(size: usize, seed: u64) -> Vec<f32> {
+    let n = size * size;
+    let sigma = 1.5f32;
+
+    // Gaussian energy kernel indexed by the toroidal offset between two pixels
+    let mut kernel = vec![0.0; n];
+    for dy in 0..size {
+        for dx in 0..size {
+            let wx = dx.min(size - dx) as f32;
+            let wy = dy.min(size - dy) as f32;
+            kernel[dy * size + dx] = (-(wx * wx + wy * wy) / (2.0 * sigma * sigma)).exp();
+        }
+    }
+
+    let toggle = |energy: &mut Vec<f32>, p: usize, sign: f32| {
+        let (px, py) = (p % size, p / size);
+        for qy in 0..size {
+            for qx in 0..size {
+                let dx = (qx + size - px) % size;
+                let dy = (qy + size - py) % size;
+                energy[qy * size + qx] += sign * kernel[dy * size + dx];
+            }
+        }
+    };
+
+    let tightest_cluster = |pattern: &Vec<bool>, energy: &Vec<f32>| {
+        (0..n).filter(|&p| pattern[p]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern: &Vec<bool>, energy: &Vec<f32>| {
+        (0..n).filter(|&p| !pattern[p]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // Random initial pattern with about a tenth of the pixels set
+    let initial_count = (n / 10).max(1);
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let mut placed = 0;
+    let mut counter = 0;
+    while placed < initial_count {
+        let p = (hash(&[seed, counter]) as usize) % n;
+        counter += 1;
+        if !pattern[p] {
+            pattern[p] = true;
+            toggle(&mut energy, p, 1.0);
+            placed += 1;
+        }
+    }
+
+    // Spread the initial pattern out by moving points from clusters into voids
+    for _ in 0..n {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        toggle(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0usize; n];
+
+    // Rank the initial points by removing them from the tightest clusters first
+    let mut phase1_pattern = pattern.clone();
+    let mut phase1_energy = energy.clone();
+    for rank in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&phase1_pattern, &phase1_energy);
+        phase1_pattern[cluster] = false;
+        toggle(&mut phase1_energy, cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+
+    // Then fill the largest voids until every pixel has a rank
+    for rank in initial_count..n {
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+        ranks[void] = rank;
+    }
+
+    ranks.iter().map(|&r| (r as f32 + 0.5) / n as f32).collect()
+}
+
+/// The texture all `BlueNoiseSampler`s share. It never changes, so it is only built once.
+fn shared_blue_noise_texture() -> &'static [f32] {
+    static TEXTURE: OnceLock<Vec<f32>> = OnceLock::new();
+    TEXTURE.get_or_init(|| blue_noise_texture(BLUE_NOISE_SIZE, 0))
+}
+
+pub struct BlueNoiseSampler {
+    texture: &'static [f32],
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            texture: shared_blue_noise_texture(),
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Blue noise value for the current pixel, using a different tile offset for every
+    /// dimension so the dimensions stay uncorrelated.
+    fn offset(&self, dimension: u32) -> f32 {
+        let h = hash(&[self.seed, dimension as u64]) as usize;
+        let x = (self.pixel.0 as usize + h) % BLUE_NOISE_SIZE;
+        let y = (self.pixel.1 as usize + (h >> 16)) % BLUE_NOISE_SIZE;
+        self.texture[y * BLUE_NOISE_SIZE + x]
+    }
+
+    fn next_pair(&mut self) -> (f32, f32) {
+        // The same scrambled sequence for every pixel, so neighbouring pixels only differ in
+        // their blue noise offset
+        let seed = hash(&[self.seed, self.dimension as u64]);
+        let index = nested_uniform_scramble(self.sample_index, seed);
+        let (x, y) = sobol_2d(index);
+        let x = u32_to_float(nested_uniform_scramble(x, mix_bits(seed as u64 ^ 1) as u32));
+        let y = u32_to_float(nested_uniform_scramble(y, mix_bits(seed as u64 ^ 2) as u32));
+
+        let (ox, oy) = (self.offset(2 * self.dimension), self.offset(2 * self.dimension + 1));
+        self.dimension += 1;
+        ((x + ox).fract().min(ONE_MINUS_EPSILON), (y + oy).fract().min(ONE_MINUS_EPSILON))
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel = (x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.next_pair().0
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        self.next_pair()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_samplers(spp: u32) -> Vec<Box<dyn Sampler>> {
+        [
+            SamplerType::Independent,
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+            SamplerType::BlueNoise,
+        ]
+        .iter()
+        .map(|t| t.create(spp, 0))
+        .collect()
+    }
+
+    #[test]
+    fn test_samples_in_unit_range() {
+        for mut sampler in all_samplers(16) {
+            for i in 0..16 {
+                sampler.start_pixel_sample(3, 7, i);
+                for _ in 0..20 {
+                    let (u, v) = sampler.get_2d();
+                    let w = sampler.get_1d();
+                    assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v) && (0.0..1.0).contains(&w));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_seed_determines_samples() {
+        let draw = |t: SamplerType, seed: u64| {
+            let mut sampler = t.create(8, seed);
+            let mut values = Vec::new();
+            for (x, y, i) in [(4, 2, 3), (0, 0, 0), (4, 2, 3)] {
+                sampler.start_pixel_sample(x, y, i);
+                values.push(sampler.get_1d());
+                values.push(sampler.get_2d().1);
+            }
+            values
+        };
+        for t in [SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol] {
+            let values = draw(t, 1);
+            // Revisiting a pixel sample reproduces it, regardless of what was rendered between
+            assert_eq!(values[0..2], values[4..6]);
+            assert_eq!(values, draw(t, 1));
+            assert_ne!(values, draw(t, 2));
+        }
+    }
+
+    /// Counts how many of the `n * n` cells of a grid get a 2D sample from the first `n * n`
+    /// samples of a pixel, for a well stratified sampler that is all of them.
+    fn occupied_cells(sampler: &mut dyn Sampler, n: u32, dimension_skip: u32) -> usize {
+        let mut cells = vec![false; (n * n) as usize];
+        for i in 0..n * n {
+            sampler.start_pixel_sample(5, 9, i);
+            for _ in 0..dimension_skip {
+                sampler.get_2d();
+            }
+            let (u, v) = sampler.get_2d();
+            cells[((v * n as f32) as u32 * n + (u * n as f32) as u32) as usize] = true;
+        }
+        cells.iter().filter(|&&c| c).count()
+    }
+
+    #[test]
+    fn test_stratification() {
+        for dimension_skip in [0, 3] {
+            assert_eq!(occupied_cells(&mut StratifiedSampler::new(16, true, 0), 4, dimension_skip), 16);
+            assert_eq!(occupied_cells(&mut SobolSampler::new(0), 4, dimension_skip), 16);
+        }
+        // Halton is stratified in its own bases, 2 and 3 for the first dimensions
+        let mut cells = [false; 6];
+        let mut halton = HaltonSampler::new(0);
+        for i in 0..6 {
+            halton.start_pixel_sample(1, 2, i);
+            let (u, v) = halton.get_2d();
+            cells[(v * 3.0) as usize * 2 + (u * 2.0) as usize] = true;
+        }
+        assert!(cells.iter().all(|&c| c));
+    }
+
+    #[test]
+    fn test_permutation_element_is_permutation() {
+        for l in [1, 5, 16, 100] {
+            let mut seen = vec![false; l as usize];
+            for i in 0..l {
+                seen[permutation_element(i, l, 0x1234567) as usize] = true;
+            }
+            assert!(seen.iter().all(|&s| s));
+        }
+    }
+
+    #[test]
+    fn test_blue_noise_texture_is_a_ranking() {
+        let texture = blue_noise_texture(16, 1);
+        let mut sorted = texture.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        for (i, v) in sorted.iter().enumerate() {
+            assert!((v - (i as f32 + 0.5) / 256.0).abs() < 1e-6);
+        }
+        // Samplers share the texture instead of building their own
+        let (a, b) = (BlueNoiseSampler::new(1), BlueNoiseSampler::new(2));
+        assert!(std::ptr::eq(a.texture, b.texture));
+    }
+}
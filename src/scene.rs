@@ -0,0 +1,117 @@
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::environment::EnvironmentMap;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::light::Light;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::sky::PreethamSky;
+use crate::vec3::{Point3, Vec3};
+
+/// What a ray sees when it escapes the scene.
+pub enum Background {
+    /// The white to blue sky gradient.
+    Gradient,
+    Solid(Color),
+    /// HDR environment map, which is also importance sampled as a light.
+    Environment(Rc<EnvironmentMap>),
+    /// Analytic daylight sky, see `Scene::set_sky` to also get a matching sun.
+    Sky(PreethamSky),
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Gradient => {
+                let unit_direction = Vec3::normalize(&ray.direction());
+                let a = 0.5*(unit_direction.y() + 1.0);
+                Color::build(1.0, 1.0, 1.0) * (1.0 - a) + Color::build(0.5, 0.7, 1.0) * a
+            }
+            Background::Solid(color) => *color,
+            Background::Environment(env) => env.radiance(&ray.direction()),
+            Background::Sky(sky) => sky.radiance(&ray.direction()),
+        }
+    }
+
+    /// The environment map if the background can be sampled as a light.
+    pub fn environment(&self) -> Option<&EnvironmentMap> {
+        match self {
+            Background::Environment(env) => Some(env),
+            _ => None,
+        }
+    }
+}
+
+/// Everything the camera needs to render: the objects, which of them are lights, lights
+/// without a surface and the background.
+pub struct Scene {
+    pub world: HittableList,
+    /// Emissive objects that are sampled directly at every diffuse hit. Every emissive object
+    /// should be registered here, emission of other objects is only picked up by specular paths.
+    pub lights: HittableList,
+    /// Point, spot and directional lights, sampled with a shadow ray each at every diffuse hit.
+    pub punctual_lights: Vec<Light>,
+    pub background: Background,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            world: HittableList::new(),
+            lights: HittableList::new(),
+            punctual_lights: Vec::new(),
+            background: Background::Gradient,
+        }
+    }
+
+    pub fn add(&mut self, object: Rc<dyn Hittable>) {
+        self.world.add(object);
+    }
+
+    /// Adds an emissive object to both the world and the light list.
+    pub fn add_light(&mut self, light: Rc<dyn Hittable>) {
+        self.world.add(light.clone());
+        self.lights.add(light);
+    }
+
+    pub fn add_punctual_light(&mut self, light: Light) {
+        self.punctual_lights.push(light);
+    }
+
+    /// Uses the analytic sky as background and adds its sun disk as a directional light.
+    pub fn set_sky(&mut self, sky: PreethamSky, sun_irradiance: f32) {
+        self.add_punctual_light(sky.sun_light(sun_irradiance));
+        self.background = Background::Sky(sky);
+    }
+
+    /// Whether there is anything `sample_light_direction` can sample.
+    pub fn has_sampled_lights(&self) -> bool {
+        !self.lights.objects.is_empty() || self.background.environment().is_some()
+    }
+
+    /// Density of `sample_light_direction` producing `direction` from `origin`. Area lights and
+    /// the environment map are each picked half of the time when both are present.
+    pub fn light_pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let lights = HittablePdf::new(&self.lights, origin);
+        match (self.lights.objects.is_empty(), self.background.environment()) {
+            (true, None) => 0.0,
+            (false, None) => lights.value(direction),
+            (true, Some(env)) => env.value(direction),
+            (false, Some(env)) => MixturePdf::new(&lights, env).value(direction),
+        }
+    }
+
+    /// Random direction from `origin` towards one of the area lights or the environment map.
+    pub fn sample_light_direction(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let lights = HittablePdf::new(&self.lights, origin);
+        match (self.lights.objects.is_empty(), self.background.environment()) {
+            (true, None) => Vec3::build(1.0, 0.0, 0.0),
+            (false, None) => lights.generate(sampler),
+            (true, Some(env)) => env.generate(sampler),
+            (false, Some(env)) => MixturePdf::new(&lights, env).generate(sampler),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::f32::consts::PI;
+
+use crate::color::Color;
+use crate::light::{DirectionalLight, Light};
+use crate::vec3::Vec3;
+
+/// Analytic daylight sky from Preetham, Shirley and Smits, "A Practical Analytic Model for
+/// Daylight" (1999). The sky color only depends on the sun direction and the turbidity, the
+/// amount of haze in the air: 2 is a very clear day, 6 is hazy and 10 is close to overcast.
+#[derive(Copy, Clone)]
+pub struct PreethamSky {
+    sun_direction: Vec3,
+    turbidity: f32,
+    /// Scale from luminance in kcd/m^2 to the renderer's radiance units.
+    pub intensity: f32,
+    theta_sun: f32,
+    zenith: [f32; 3],
+    perez: [[f32; 5]; 3],
+}
+
+impl PreethamSky {
+    /// `sun_direction` points towards the sun and has to be above the horizon.
+    pub fn new(sun_direction: Vec3, turbidity: f32) -> Self {
+        let sun_direction = Vec3::normalize(&sun_direction);
+        let t = turbidity.clamp(1.7, 10.0);
+        let theta_sun = sun_direction.y.clamp(0.0, 1.0).acos();
+
+        // Perez distribution coefficients for luminance Y and the chromaticities x and y
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let (x, y) = zenith_chromaticity(t, theta_sun);
+        Self {
+            sun_direction,
+            turbidity: t,
+            intensity: 0.1,
+            theta_sun,
+            zenith: [zenith_luminance(t, theta_sun), x, y],
+            perez,
+        }
+    }
+
+    /// Perez et al. all-weather sky distribution function.
+    fn perez(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = *coefficients;
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+
+    /// Luminance (kcd/m^2) and chromaticity of the sky in `direction`.
+    pub fn xyy(&self, direction: &Vec3) -> (f32, f32, f32) {
+        // Directions below the horizon reuse the sky just above it
+        let mut d = Vec3::normalize(direction);
+        d.y = d.y.max(0.001);
+        let d = Vec3::normalize(&d);
+
+        let cos_theta = d.y;
+        let gamma = Vec3::dot(&d, &self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let mut values = [0.0; 3];
+        for (i, value) in values.iter_mut().enumerate() {
+            let ratio = Self::perez(&self.perez[i], cos_theta, gamma)
+                / Self::perez(&self.perez[i], 1.0, self.theta_sun);
+            *value = self.zenith[i] * ratio;
+        }
+
+        (values[0], values[1], values[2])
+    }
+
+    /// Linear sRGB radiance of the sky in `direction`.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let (luminance, x, y) = self.xyy(direction);
+        xyy_to_rgb(luminance, x, y) * self.intensity
+    }
+
+    /// Sun to go with the sky, colored by how much the atmosphere attenuates it at the current
+    /// elevation. `irradiance` is the strength of the sun before attenuation.
+    pub fn sun_light(&self, irradiance: f32) -> Light {
+        let transmittance = self.sun_transmittance();
+        Light::Directional(DirectionalLight::new(self.sun_direction, transmittance * irradiance, 0.53))
+    }
+
+    /// Rayleigh and aerosol transmittance of sunlight through the atmosphere, evaluated at
+    /// representative wavelengths for red, green and blue.
+    pub fn sun_transmittance(&self) -> Color {
+        let theta_degrees = self.theta_sun.to_degrees();
+        // Relative optical air mass (Kasten and Young)
+        let m = 1.0 / (self.theta_sun.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let alpha = 1.3;
+
+        let channel = |lambda_um: f32| {
+            let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * m).exp();
+            let aerosol = (-beta * lambda_um.powf(-alpha) * m).exp();
+            rayleigh * aerosol
+        };
+
+        Color::build(channel(0.680), channel(0.550), channel(0.440))
+    }
+}
+
+/// Zenith luminance in kcd/m^2 for a turbidity and sun zenith angle (radians).
+pub fn zenith_luminance(turbidity: f32, theta_sun: f32) -> f32 {
+    let t = turbidity;
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+    (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192
+}
+
+/// CIE xy chromaticity of the zenith for a turbidity and sun zenith angle (radians).
+pub fn zenith_chromaticity(turbidity: f32, theta_sun: f32) -> (f32, f32) {
+    let t = turbidity;
+    let t2 = t * t;
+    let th = theta_sun;
+    let th2 = th * th;
+    let th3 = th2 * th;
+
+    let x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+        + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+        + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+    let y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+        + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+        + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+    (x, y)
+}
+
+/// Converts CIE xyY to linear sRGB.
+fn xyy_to_rgb(luminance: f32, x: f32, y: f32) -> Color {
+    if y <= 0.0 {
+        return Color::new();
+    }
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+    Color::build(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raytracing::degrees_to_radians;
+
+    // Turbidity and sun zenith angle in degrees
+    const CONDITIONS: [(f32, f32); 5] = [(2.0, 0.0), (2.0, 30.0), (3.0, 45.0), (6.0, 60.0), (10.0, 30.0)];
+
+    /// Correlated color temperature from McCamy, "Correlated color temperature as an explicit
+    /// function of chromaticity coordinates", Color Research & Application 17(2), 1992.
+    fn mccamy_cct(x: f32, y: f32) -> f32 {
+        let n = (x - 0.3320) / (0.1858 - y);
+        449.0 * n * n * n + 3525.0 * n * n + 6823.3 * n + 5520.33
+    }
+
+    #[test]
+    fn test_zenith_chromaticity_lies_on_daylight_locus() {
+        for (t, theta) in CONDITIONS {
+            let (x, y) = zenith_chromaticity(t, degrees_to_radians(theta));
+            // CIE daylight locus, CIE 015:2004 Colorimetry, section 3.1
+            let locus = -3.000 * x * x + 2.870 * x - 0.275;
+            assert!((y - locus).abs() < 0.012, "T={} theta={}: ({}, {})", t, theta, x, y);
+            // Clear to hazy skies seen at the zenith are bluer than the sun, between 8000K
+            // and 25000K
+            let cct = mccamy_cct(x, y);
+            assert!((8000.0..25000.0).contains(&cct), "T={} theta={}: {}K", t, theta, cct);
+        }
+        // Haze whitens the sky
+        let clear = zenith_chromaticity(2.0, degrees_to_radians(30.0));
+        let hazy = zenith_chromaticity(10.0, degrees_to_radians(30.0));
+        assert!(mccamy_cct(hazy.0, hazy.1) < mccamy_cct(clear.0, clear.1));
+    }
+
+    #[test]
+    fn test_zenith_luminance_trends() {
+        for (t, theta) in CONDITIONS {
+            assert!(zenith_luminance(t, degrees_to_radians(theta)) > 0.0);
+        }
+        // The zenith darkens as the sun sets and brightens with haze
+        let luminance = |t: f32, theta: f32| zenith_luminance(t, degrees_to_radians(theta));
+        assert!(luminance(2.0, 0.0) > luminance(2.0, 30.0));
+        assert!(luminance(2.0, 30.0) > luminance(2.0, 60.0));
+        assert!(luminance(10.0, 30.0) > luminance(2.0, 30.0));
+    }
+
+    #[test]
+    fn test_zenith_luminance_matches_published_fit() {
+        // Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight", SIGGRAPH 1999,
+        // publish the zenith luminance as a fit rather than a table (Appendix A.2):
+        //   Y_z = (4.0453 T - 4.9710) tan(chi) - 0.2155 T + 2.4192 kcd/m^2,
+        //   chi = (4/9 - T/120) (pi - 2 theta_s).
+        // The values below evaluate that equation in double precision.
+        const PUBLISHED: [(f32, f32, f32); 5] = [
+            // turbidity, sun elevation (degrees), Y_z (kcd/m^2)
+            (2.0, 90.0, 15.5007),
+            (2.0, 60.0, 5.8867),
+            (3.0, 45.0, 7.3204),
+            (6.0, 30.0, 9.5853),
+            (10.0, 60.0, 33.7397),
+        ];
+        for (t, elevation, expected) in PUBLISHED {
+            let luminance = zenith_luminance(t, degrees_to_radians(90.0 - elevation));
+            // Within 0.1%
+            assert!((luminance - expected).abs() < 1e-3 * expected, "T={} elevation={}: {}", t, elevation, luminance);
+        }
+    }
+
+    #[test]
+    fn test_sky_zenith_equals_zenith_values() {
+        let theta = degrees_to_radians(45.0);
+        let sky = PreethamSky::new(Vec3::build(theta.sin(), theta.cos(), 0.0), 3.0);
+        let (luminance, x, y) = sky.xyy(&Vec3::build(0.0, 1.0, 0.0));
+        let (zx, zy) = zenith_chromaticity(3.0, theta);
+        assert!((luminance - zenith_luminance(3.0, theta)).abs() < 1e-3 * luminance);
+        assert!((x - zx).abs() < 1e-4);
+        assert!((y - zy).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_sky_is_bluish_and_brighter_near_sun() {
+        let theta = degrees_to_radians(60.0);
+        let sun = Vec3::build(theta.sin(), theta.cos(), 0.0);
+        let sky = PreethamSky::new(sun, 2.5);
+        let zenith = sky.radiance(&Vec3::build(0.0, 1.0, 0.0));
+        assert!(zenith.z > zenith.x);
+        let near_sun = sky.radiance(&Vec3::build(theta.sin(), theta.cos() + 0.1, 0.0));
+        let away = sky.radiance(&Vec3::build(-theta.sin(), theta.cos() + 0.1, 0.0));
+        assert!(near_sun.y > away.y);
+    }
+
+    #[test]
+    fn test_low_sun_is_reddened() {
+        let low = PreethamSky::new(Vec3::build(1.0, 0.05, 0.0), 3.0).sun_transmittance();
+        let high = PreethamSky::new(Vec3::build(0.0, 1.0, 0.0), 3.0).sun_transmittance();
+        assert!(low.x > low.z);
+        assert!(high.z > low.z);
+    }
+}
@@ -1,7 +1,14 @@
+use std::f32::consts::PI;
+
 use crate::hittable::{Hittable, HitRecord};
 use crate::vec3::{Point3, Vec3};
 use crate::interval::Interval;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler::{sample_uniform_sphere, Sampler};
 use crate::material::{Material, Lambertian};
+use crate::stats::{self, Counter};
+use raytracing::rng::Rng;
 
 pub struct Sphere {
     center: Point3,
@@ -20,7 +27,8 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &crate::ray::Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &crate::ray::Ray, ray_t: &Interval, rec: &mut HitRecord, _rng: &mut Rng) -> bool {
+        stats::count(Counter::SphereTests);
         let oc: Vec3 = self.center - r.origin();
         let a = r.direction().length_squared();
         let h = Vec3::dot(&r.direction(), &oc);
@@ -33,10 +41,10 @@ impl Hittable for Sphere {
         } 
 
         let sqrtd = discriminant.sqrt();
-        let root = (h - sqrtd) / a;
+        let mut root = (h - sqrtd) / a;
         if !ray_t.surrounds(root) {
             // if the root is not within the ray's interval, check the other root
-            let root = (h + sqrtd) / a;
+            root = (h + sqrtd) / a;
             if !ray_t.surrounds(root) {
                 return false;
             }
@@ -49,10 +57,73 @@ impl Hittable for Sphere {
         let outward_normal = (rec.point - self.center) / self.radius;
         // sets the normal and front_face depending on direction
         rec.set_face_normal(r, &outward_normal);
+        // Longitude around the y axis starting at -x, latitude from -y to +y
+        let theta = (-outward_normal.y).clamp(-1.0, 1.0).acos();
+        let phi = (-outward_normal.z).atan2(outward_normal.x) + PI;
+        rec.u = phi / (2.0 * PI);
+        rec.v = theta / PI;
 
         rec.material = self.material;
 
         return true;
     }
 
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0)) {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // Inside the sphere every direction hits it, fall back to uniform sampling
+            return 1.0 / (4.0 * PI);
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return sample_uniform_sphere(sampler.get_2d());
+        }
+
+        // Sample uniformly inside the cone of directions the sphere subtends
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let (u1, u2) = sampler.get_2d();
+        let z = 1.0 + u1 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * u2;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        Onb::new(&direction).transform(&Vec3::build(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SobolSampler;
+
+    #[test]
+    fn test_sampled_directions_hit_the_sphere() {
+        let sphere = Sphere::new(&Point3::build(1.0, 2.0, -4.0), 0.5, Material::default());
+        let origin = Point3::new();
+        let mut sampler = SobolSampler::new(0);
+        for i in 0..256 {
+            sampler.start_pixel_sample(0, 0, i);
+            let direction = sphere.random(&origin, &mut sampler);
+            let pdf = sphere.pdf_value(&origin, &direction);
+            // Uniform over the cone the sphere subtends
+            let distance_squared: f32 = 1.0 + 4.0 + 16.0;
+            let solid_angle = 2.0 * PI * (1.0 - (1.0 - 0.25 / distance_squared).sqrt());
+            assert!((pdf * solid_angle - 1.0).abs() < 1e-3, "{}", pdf);
+        }
+        assert_eq!(sphere.pdf_value(&origin, &Vec3::build(0.0, 0.0, 1.0)), 0.0);
+    }
 }
@@ -0,0 +1,168 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+/// Events counted while rendering.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Counter {
+    CameraRays,
+    /// Rays continuing a path after a bounce.
+    SecondaryRays,
+    /// Rays testing the visibility of a light sample.
+    ShadowRays,
+    /// Surface interactions along all paths, divided by the camera rays this gives the
+    /// average path length.
+    PathVertices,
+    SphereTests,
+    QuadTests,
+    TriangleTests,
+    SdfTests,
+    MediumTests,
+    VolumeTests,
+    /// Ray-box slab tests. The scene has no BVH yet, so these only come from the bounds of
+    /// heterogeneous volumes; with one they would count its node visits.
+    BoxTests,
+}
+
+impl Counter {
+    pub const COUNT: usize = 11;
+    pub const ALL: [Counter; Counter::COUNT] = [
+        Counter::CameraRays,
+        Counter::SecondaryRays,
+        Counter::ShadowRays,
+        Counter::PathVertices,
+        Counter::SphereTests,
+        Counter::QuadTests,
+        Counter::TriangleTests,
+        Counter::SdfTests,
+        Counter::MediumTests,
+        Counter::VolumeTests,
+        Counter::BoxTests,
+    ];
+
+    /// Name used in the printed summary and as JSON key.
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::CameraRays => "camera_rays",
+            Counter::SecondaryRays => "secondary_rays",
+            Counter::ShadowRays => "shadow_rays",
+            Counter::PathVertices => "path_vertices",
+            Counter::SphereTests => "sphere_tests",
+            Counter::QuadTests => "quad_tests",
+            Counter::TriangleTests => "triangle_tests",
+            Counter::SdfTests => "sdf_tests",
+            Counter::MediumTests => "medium_tests",
+            Counter::VolumeTests => "volume_tests",
+            Counter::BoxTests => "box_tests",
+        }
+    }
+}
+
+thread_local! {
+    static COUNTERS: [Cell<u64>; Counter::COUNT] = Default::default();
+}
+
+/// Counts one `counter` event on the current thread.
+pub fn count(counter: Counter) {
+    COUNTERS.with(|counters| {
+        let cell = &counters[counter as usize];
+        cell.set(cell.get() + 1);
+    });
+}
+
+/// Returns the current thread's counts and resets them to zero.
+pub fn take() -> Counts {
+    COUNTERS.with(|counters| Counts(counters.each_ref().map(|cell| cell.take())))
+}
+
+/// Snapshot of all counters.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Counts(pub [u64; Counter::COUNT]);
+
+impl Counts {
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.0[counter as usize]
+    }
+}
+
+/// Counters and phase timings of one render, see `RenderSummary::stats`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderStats {
+    pub counts: Counts,
+    /// Preparing the render: creating the sampler, fingerprinting the scene, loading a checkpoint.
+    pub setup_time: Duration,
+    /// Tracing paths.
+    pub sampling_time: Duration,
+    /// Writing images and checkpoints after sampling.
+    pub output_time: Duration,
+}
+
+impl RenderStats {
+    pub fn rays(&self) -> u64 {
+        [Counter::CameraRays, Counter::SecondaryRays, Counter::ShadowRays].iter().map(|&c| self.counts.get(c)).sum()
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays() as f64 / self.sampling_time.as_secs_f64().max(1e-9)
+    }
+
+    /// Average number of surface interactions per camera ray.
+    pub fn average_path_length(&self) -> f64 {
+        self.counts.get(Counter::PathVertices) as f64 / self.counts.get(Counter::CameraRays).max(1) as f64
+    }
+
+    /// Human readable table of all statistics.
+    pub fn summary(&self) -> String {
+        let mut summary = String::from("Render statistics\n");
+        for counter in Counter::ALL {
+            summary += &format!("  {:<24}{:>14}\n", counter.name(), self.counts.get(counter));
+        }
+        summary += &format!("  {:<24}{:>14.2}\n", "average_path_length", self.average_path_length());
+        summary += &format!("  {:<24}{:>14.0}\n", "rays_per_second", self.rays_per_second());
+        for (name, time) in [("setup_time", self.setup_time), ("sampling_time", self.sampling_time), ("output_time", self.output_time)] {
+            summary += &format!("  {:<24}{:>13.3}s\n", name, time.as_secs_f64());
+        }
+        summary
+    }
+
+    /// The statistics as a flat JSON object, times in seconds.
+    pub fn to_json(self) -> String {
+        let mut fields: Vec<String> = Counter::ALL
+            .iter()
+            .map(|&counter| format!("\"{}\": {}", counter.name(), self.counts.get(counter)))
+            .collect();
+        fields.push(format!("\"average_path_length\": {}", self.average_path_length()));
+        fields.push(format!("\"rays_per_second\": {}", self.rays_per_second()));
+        fields.push(format!("\"setup_time\": {}", self.setup_time.as_secs_f64()));
+        fields.push(format!("\"sampling_time\": {}", self.sampling_time.as_secs_f64()));
+        fields.push(format!("\"output_time\": {}", self.output_time.as_secs_f64()));
+        format!("{{\n  {}\n}}\n", fields.join(",\n  "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_resets_counters() {
+        take();
+        count(Counter::ShadowRays);
+        count(Counter::ShadowRays);
+        count(Counter::SphereTests);
+        let counts = take();
+        assert_eq!(counts.get(Counter::ShadowRays), 2);
+        assert_eq!(counts.get(Counter::SphereTests), 1);
+        assert_eq!(counts.get(Counter::CameraRays), 0);
+        assert_eq!(take(), Counts::default());
+    }
+
+    #[test]
+    fn test_json_has_every_counter() {
+        let mut stats = RenderStats::default();
+        stats.counts.0[Counter::CameraRays as usize] = 10;
+        stats.counts.0[Counter::PathVertices as usize] = 25;
+        let json = stats.to_json();
+        assert!(Counter::ALL.iter().all(|c| json.contains(&format!("\"{}\": ", c.name()))));
+        assert!(json.contains("\"average_path_length\": 2.5"));
+    }
+}
@@ -0,0 +1,167 @@
+/// Rectangular block of pixels, from (x0, y0) up to but excluding (x1, y1).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn pixel_count(&self) -> u32 {
+        self.width() * self.height()
+    }
+
+    /// The tile's pixels, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y0..self.y1).flat_map(move |y| (self.x0..self.x1).map(move |x| (x, y)))
+    }
+}
+
+/// Order in which the tiles of an image are rendered.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    Row,
+    /// Outwards from the center of the image, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, consecutive tiles are always neighbours which keeps the scene
+    /// data they touch in the cache.
+    Hilbert,
+}
+
+/// Splits a `width * height` image into tiles of `tile_size` pixels square, with smaller tiles
+/// at the right and bottom edges, and sorts them in `order`.
+pub fn tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let nx = width.div_ceil(tile_size);
+    let ny = height.div_ceil(tile_size);
+    let tile = |tx: u32, ty: u32| Tile {
+        x0: tx * tile_size,
+        y0: ty * tile_size,
+        x1: ((tx + 1) * tile_size).min(width),
+        y1: ((ty + 1) * tile_size).min(height),
+    };
+
+    let coordinates = match order {
+        TileOrder::Row => (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect(),
+        TileOrder::Spiral => spiral(nx, ny),
+        TileOrder::Hilbert => hilbert(nx, ny),
+    };
+    coordinates.into_iter().map(|(tx, ty)| tile(tx, ty)).collect()
+}
+
+/// Walks a square spiral around the center tile and keeps the positions inside the grid.
+fn spiral(nx: u32, ny: u32) -> Vec<(u32, u32)> {
+    let total = (nx * ny) as usize;
+    let mut result = Vec::with_capacity(total);
+    let (mut x, mut y) = (((nx as i64) - 1) / 2, ((ny as i64) - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg_length = 1;
+    let mut direction = 0;
+
+    let push = |x: i64, y: i64, result: &mut Vec<(u32, u32)>| {
+        if x >= 0 && y >= 0 && x < nx as i64 && y < ny as i64 {
+            result.push((x as u32, y as u32));
+        }
+    };
+    push(x, y, &mut result);
+    while result.len() < total {
+        // Legs grow by one every second turn: 1, 1, 2, 2, 3, 3, ...
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction];
+            for _ in 0..leg_length {
+                x += dx;
+                y += dy;
+                push(x, y, &mut result);
+            }
+            direction = (direction + 1) % 4;
+        }
+        leg_length += 1;
+    }
+    result
+}
+
+/// Positions along a Hilbert curve over the smallest power of two square covering the grid,
+/// keeping the ones inside it.
+fn hilbert(nx: u32, ny: u32) -> Vec<(u32, u32)> {
+    let n = nx.max(ny).max(1).next_power_of_two();
+    (0..n as u64 * n as u64)
+        .map(|d| hilbert_position(n, d))
+        .filter(|&(x, y)| x < nx && y < ny)
+        .collect()
+}
+
+/// Position of the `d`th cell on the Hilbert curve through an `n * n` grid.
+fn hilbert_position(n: u32, d: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut t = d;
+    let mut s = 1;
+    while s < n {
+        let rx = (1 & (t / 2)) as u32;
+        let ry = (1 & (t ^ rx as u64)) as u32;
+        // Rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers_image_once(width: u32, height: u32, tiles: &[Tile]) -> bool {
+        let mut seen = vec![0; (width * height) as usize];
+        for tile in tiles {
+            for (x, y) in tile.pixels() {
+                seen[(y * width + x) as usize] += 1;
+            }
+        }
+        seen.iter().all(|&s| s == 1)
+    }
+
+    #[test]
+    fn test_every_order_covers_the_image() {
+        for order in [TileOrder::Row, TileOrder::Spiral, TileOrder::Hilbert] {
+            for (width, height) in [(64, 64), (100, 37), (5, 90)] {
+                let tiles = tiles(width, height, 16, order);
+                assert_eq!(tiles.len() as u32, width.div_ceil(16) * height.div_ceil(16));
+                assert!(covers_image_once(width, height, &tiles), "{:?} {}x{}", order, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_in_the_center() {
+        let tiles = tiles(80, 48, 16, TileOrder::Spiral);
+        assert_eq!((tiles[0].x0, tiles[0].y0), (32, 16));
+    }
+
+    #[test]
+    fn test_hilbert_steps_to_neighbours() {
+        let tiles = tiles(128, 128, 16, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let dx = (pair[0].x0 as i64 - pair[1].x0 as i64).abs();
+            let dy = (pair[0].y0 as i64 - pair[1].y0 as i64).abs();
+            assert_eq!(dx + dy, 16);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+use crate::sampler::Sampler;
+use crate::stats::{self, Counter};
+use raytracing::rng::Rng;
+
+/// Triangle with counter clockwise vertices when seen from the front face.
+pub struct Triangle {
+    v0: Point3,
+    edge1: Vec3,
+    edge2: Vec3,
+    normal: Vec3,
+    area: f32,
+    pub material: Material,
+}
+
+impl Triangle {
+    pub fn new(v0: &Point3, v1: &Point3, v2: &Point3, material: Material) -> Self {
+        let edge1 = *v1 - *v0;
+        let edge2 = *v2 - *v0;
+        let n = Vec3::cross(&edge1, &edge2);
+
+        Self {
+            v0: *v0,
+            edge1,
+            edge2,
+            normal: Vec3::normalize(&n),
+            area: 0.5 * n.length(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller-Trumbore intersection
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord, _rng: &mut Rng) -> bool {
+        stats::count(Counter::TriangleTests);
+        let p = Vec3::cross(&r.direction(), &self.edge2);
+        let det = Vec3::dot(&self.edge1, &p);
+        if det.abs() < 1e-8 {
+            return false;
+        }
+        let inv_det = 1.0 / det;
+
+        let s = r.origin() - self.v0;
+        let b1 = Vec3::dot(&s, &p) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return false;
+        }
+
+        let q = Vec3::cross(&s, &self.edge1);
+        let b2 = Vec3::dot(&r.direction(), &q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return false;
+        }
+
+        let t = Vec3::dot(&self.edge2, &q) * inv_det;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.point = r.at(t);
+        // Barycentric coordinates of the second and third vertex
+        rec.u = b1;
+        rec.v = b2;
+        rec.set_face_normal(r, &self.normal);
+        rec.material = self.material;
+
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0)) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (Vec3::dot(direction, &self.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        // Uniform barycentric coordinates over the triangle
+        let (u1, u2) = sampler.get_2d();
+        let su = u1.sqrt();
+        let b1 = 1.0 - su;
+        let b2 = u2 * su;
+        let p = self.v0 + self.edge1 * b1 + self.edge2 * b2;
+        p - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SobolSampler;
+
+    fn triangle() -> Triangle {
+        // Right triangle in the z = 0 plane, counter clockwise seen from +z
+        Triangle::new(&Point3::new(), &Point3::build(1.0, 0.0, 0.0), &Point3::build(0.0, 1.0, 0.0), Material::default())
+    }
+
+    fn hit(triangle: &Triangle, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        triangle
+            .hit(&Ray::new(&origin, &direction), &Interval::with_bounds(0.001, f32::INFINITY), &mut rec, &mut Rng::new(0))
+            .then_some(rec)
+    }
+
+    #[test]
+    fn test_triangle_hits() {
+        let triangle = triangle();
+        let down = Vec3::build(0.0, 0.0, -1.0);
+        let rec = hit(&triangle, Point3::build(0.25, 0.5, 3.0), down).unwrap();
+        assert_eq!(rec.t, 3.0);
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::build(0.0, 0.0, 1.0));
+        assert!(!hit(&triangle, Point3::build(0.25, 0.5, -1.0), -down).unwrap().front_face);
+
+        // Edges belong to the triangle, including the hypotenuse
+        assert!(hit(&triangle, Point3::build(0.5, 0.0, 1.0), down).is_some());
+        assert!(hit(&triangle, Point3::build(0.5, 0.5, 1.0), down).is_some());
+
+        assert!(hit(&triangle, Point3::build(0.6, 0.6, 1.0), down).is_none());
+        assert!(hit(&triangle, Point3::build(-0.01, 0.5, 1.0), down).is_none());
+        // Parallel to the plane
+        assert!(hit(&triangle, Point3::build(-1.0, 0.2, 1.0), Vec3::build(1.0, 0.0, 0.0)).is_none());
+        assert!(hit(&triangle, Point3::build(-1.0, 0.2, 0.0), Vec3::build(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_triangle_pdf_matches_solid_angle() {
+        let origin = Point3::build(0.2, 0.3, 0.7);
+        let triangle = triangle();
+        // Van Oosterom and Strackee's solid angle of a triangle
+        let (a, b, c) = (Point3::new() - origin, Point3::build(1.0, 0.0, 0.0) - origin, Point3::build(0.0, 1.0, 0.0) - origin);
+        let (la, lb, lc) = (a.length(), b.length(), c.length());
+        let numerator = Vec3::dot(&a, &Vec3::cross(&b, &c)).abs();
+        let denominator = la * lb * lc + Vec3::dot(&a, &b) * lc + Vec3::dot(&a, &c) * lb + Vec3::dot(&b, &c) * la;
+        let exact = 2.0 * numerator.atan2(denominator);
+
+        let mut sampler = SobolSampler::new(0);
+        let mut sum = 0.0;
+        for i in 0..1024 {
+            sampler.start_pixel_sample(0, 0, i);
+            let direction = triangle.random(&origin, &mut sampler);
+            let pdf = triangle.pdf_value(&origin, &direction);
+            assert!(pdf > 0.0);
+            sum += 1.0 / pdf;
+        }
+        assert!((sum / 1024.0 - exact).abs() < 0.01 * exact, "{} {}", sum / 1024.0, exact);
+        assert_eq!(triangle.pdf_value(&origin, &Vec3::build(0.0, 0.0, 1.0)), 0.0);
+    }
+}
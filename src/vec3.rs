@@ -1,7 +1,9 @@
 #![allow(dead_code)]
 
-use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign};
+use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign, Index};
 use float_cmp::approx_eq;
+use raytracing::{random_float, random_range};
+use raytracing::rng::Rng;
 
 pub type Point3 = Vec3;
 
@@ -53,20 +55,20 @@ impl Vec3 {
 
 
     /// Returns a new `Vec3` with random components in the range [0.0, 1.0).
-    pub fn random() -> Self {
+    pub fn random(rng: &mut Rng) -> Self {
         Vec3::build(
-            rand::random::<f32>(),
-            rand::random::<f32>(),
-            rand::random::<f32>()
+            random_float(rng),
+            random_float(rng),
+            random_float(rng)
         )
     }
 
     /// Returns a new `Vec3` with random components in the range [min, max).
-    pub fn random_range(min: f32, max: f32) -> Self {
+    pub fn random_range(rng: &mut Rng, min: f32, max: f32) -> Self {
         Vec3::build(
-            rand::random::<f32>() * (max - min) + min,
-            rand::random::<f32>() * (max - min) + min,
-            rand::random::<f32>() * (max - min) + min
+            random_range(rng, min, max),
+            random_range(rng, min, max),
+            random_range(rng, min, max)
         )
     }
 
@@ -87,9 +89,9 @@ impl Vec3 {
     }
 
     /// Returns a new `Vec3` that is a random unit vector.
-    pub fn random_unit_vector() -> Self {
+    pub fn random_unit_vector(rng: &mut Rng) -> Self {
         loop {
-            let v = Vec3::random_range(-1.0, 1.0);
+            let v = Vec3::random_range(rng, -1.0, 1.0);
             let len_squared = v.length_squared();
             if 1e-160 < len_squared && len_squared < 1.0 {
                 return v / len_squared.sqrt();
@@ -99,8 +101,8 @@ impl Vec3 {
 
     /// Returns a new `Vec3` that is a random unit vector in the hemisphere defined by the normal.
     /// This is used for generating random directions for diffuse reflection.
-    pub fn random_on_hemisphere(normal: &Vec3) -> Self {
-        let on_unit_sphere = Vec3::random_unit_vector();
+    pub fn random_on_hemisphere(rng: &mut Rng, normal: &Vec3) -> Self {
+        let on_unit_sphere = Vec3::random_unit_vector(rng);
         if Vec3::dot(&on_unit_sphere, normal) > 0.0 {
             on_unit_sphere
         } else {
@@ -109,6 +111,20 @@ impl Vec3 {
     }
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    /// Returns the component along axis `index`, 0 for x, 1 for y and 2 for z.
+    fn index(&self, index: usize) -> &f32 {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of range: {}", index),
+        }
+    }
+}
+
 impl Add for Vec3 {
     type Output = Self;
 
@@ -253,6 +269,14 @@ mod tests {
         assert_eq!(v, u);
     }
 
+    #[test]
+    fn test_index() {
+        let v = Vec3::build(0.1, 0.2, 0.3);
+        assert_eq!(v[0], 0.1);
+        assert_eq!(v[1], 0.2);
+        assert_eq!(v[2], 0.3);
+    }
+
     #[test]
     fn test_add() {
         let v = Vec3::build(0.1, 0.2, 0.3);
@@ -0,0 +1,437 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::noise::fbm;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+use crate::stats::{self, Counter};
+use raytracing::rng::Rng;
+
+/// A 3D grid of density values. Implementors only provide voxel access, the trilinear
+/// lookup is shared.
+pub trait DensityGrid {
+    /// Number of voxels along x, y and z.
+    fn resolution(&self) -> (usize, usize, usize);
+
+    /// Density stored in voxel (i, j, k), indices are already clamped to the grid.
+    fn voxel(&self, i: usize, j: usize, k: usize) -> f32;
+
+    /// Upper bound of all densities in the grid, used as the majorant for tracking.
+    fn max_density(&self) -> f32;
+
+    /// Trilinearly interpolated density at `p` in normalized grid coordinates [0, 1]^3.
+    /// Voxel values are located at the cell centers.
+    fn density(&self, p: &Point3) -> f32 {
+        let (nx, ny, nz) = self.resolution();
+        let gx = p.x * nx as f32 - 0.5;
+        let gy = p.y * ny as f32 - 0.5;
+        let gz = p.z * nz as f32 - 0.5;
+
+        let (x0, y0, z0) = (gx.floor(), gy.floor(), gz.floor());
+        let (tx, ty, tz) = (gx - x0, gy - y0, gz - z0);
+
+        let clamp = |v: f32, n: usize| (v.max(0.0) as usize).min(n - 1);
+        let (i0, i1) = (clamp(x0, nx), clamp(x0 + 1.0, nx));
+        let (j0, j1) = (clamp(y0, ny), clamp(y0 + 1.0, ny));
+        let (k0, k1) = (clamp(z0, nz), clamp(z0 + 1.0, nz));
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        let c00 = lerp(self.voxel(i0, j0, k0), self.voxel(i1, j0, k0), tx);
+        let c10 = lerp(self.voxel(i0, j1, k0), self.voxel(i1, j1, k0), tx);
+        let c01 = lerp(self.voxel(i0, j0, k1), self.voxel(i1, j0, k1), tx);
+        let c11 = lerp(self.voxel(i0, j1, k1), self.voxel(i1, j1, k1), tx);
+
+        lerp(lerp(c00, c10, ty), lerp(c01, c11, ty), tz)
+    }
+}
+
+/// Densities stored in one flat array with x varying fastest.
+pub struct DenseGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f32>,
+    max: f32,
+}
+
+/// Densities the tracking estimators can handle: a negative one would make null collisions
+/// more likely than certain and NaN would be skipped by the majorant.
+fn valid_density(density: f32) -> bool {
+    density.is_finite() && density >= 0.0
+}
+
+impl DenseGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>) -> Self {
+        assert!(nx > 0 && ny > 0 && nz > 0, "grid resolution must be non-zero");
+        assert_eq!(data.len(), nx * ny * nz, "grid data does not match its resolution");
+        assert!(data.iter().all(|&d| valid_density(d)), "grid densities must be finite and non-negative");
+        let max = data.iter().cloned().fold(0.0, f32::max);
+        Self { nx, ny, nz, data, max }
+    }
+
+    /// Fills the grid by evaluating `f` at every voxel center in normalized coordinates.
+    pub fn from_fn(nx: usize, ny: usize, nz: usize, f: impl Fn(&Point3) -> f32) -> Self {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    let p = Point3::build(
+                        (i as f32 + 0.5) / nx as f32,
+                        (j as f32 + 0.5) / ny as f32,
+                        (k as f32 + 0.5) / nz as f32,
+                    );
+                    data.push(f(&p).max(0.0));
+                }
+            }
+        }
+        Self::new(nx, ny, nz, data)
+    }
+
+    /// Procedural cloud: fBm noise shaped by a spherical falloff so the density fades out
+    /// before reaching the sides of the grid.
+    pub fn cloud(resolution: usize, frequency: f32, octaves: u32, seed: u32) -> Self {
+        Self::from_fn(resolution, resolution, resolution, |p| {
+            let centered = (*p - Point3::build(0.5, 0.5, 0.5)) * 2.0;
+            let falloff = 1.0 - centered.length();
+            let noise = fbm(&(*p * frequency), octaves, seed);
+            (noise - 0.5 + falloff) * 2.0
+        })
+    }
+
+    /// Loads a grid from the raw format: three little endian `u32`s with the x, y and z
+    /// resolution, followed by `nx * ny * nz` little endian `f32` densities with x varying
+    /// fastest.
+    pub fn load_raw<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        Self::from_raw_bytes(&bytes)
+    }
+
+    pub fn from_raw_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if bytes.len() < 12 {
+            return Err(invalid("voxel grid header is truncated"));
+        }
+        let read_u32 = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        let (nx, ny, nz) = (read_u32(0), read_u32(4), read_u32(8));
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(invalid("voxel grid has a zero resolution"));
+        }
+
+        let count = nx
+            .checked_mul(ny)
+            .and_then(|n| n.checked_mul(nz))
+            .ok_or_else(|| invalid("voxel grid resolution overflows"))?;
+        let body = &bytes[12..];
+        if count.checked_mul(4) != Some(body.len()) {
+            return Err(invalid("voxel grid data does not match its resolution"));
+        }
+
+        let data: Vec<f32> = body
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        if !data.iter().all(|&d| valid_density(d)) {
+            return Err(invalid("voxel grid has a density that is negative or not finite"));
+        }
+        Ok(Self::new(nx, ny, nz, data))
+    }
+
+    pub fn to_raw_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + self.data.len() * 4);
+        for n in [self.nx, self.ny, self.nz] {
+            bytes.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        for d in &self.data {
+            bytes.extend_from_slice(&d.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn save_raw<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_raw_bytes())
+    }
+}
+
+impl DensityGrid for DenseGrid {
+    fn resolution(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.nz)
+    }
+
+    fn voxel(&self, i: usize, j: usize, k: usize) -> f32 {
+        self.data[(k * self.ny + j) * self.nx + i]
+    }
+
+    fn max_density(&self) -> f32 {
+        self.max
+    }
+}
+
+/// Grid split into cubic bricks where bricks that are entirely empty are not stored.
+/// Clouds and explosions are mostly empty space so this saves a lot of memory.
+pub struct SparseGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    brick_size: usize,
+    bricks_x: usize,
+    bricks_y: usize,
+    bricks: Vec<Option<Box<[f32]>>>,
+    max: f32,
+}
+
+impl SparseGrid {
+    pub fn from_dense(grid: &DenseGrid, brick_size: usize) -> Self {
+        let brick_size = brick_size.max(1);
+        let (nx, ny, nz) = grid.resolution();
+        let bricks_x = nx.div_ceil(brick_size);
+        let bricks_y = ny.div_ceil(brick_size);
+        let bricks_z = nz.div_ceil(brick_size);
+
+        let mut bricks = Vec::with_capacity(bricks_x * bricks_y * bricks_z);
+        for bz in 0..bricks_z {
+            for by in 0..bricks_y {
+                for bx in 0..bricks_x {
+                    let mut values = vec![0.0; brick_size * brick_size * brick_size];
+                    let mut occupied = false;
+                    for z in 0..brick_size {
+                        for y in 0..brick_size {
+                            for x in 0..brick_size {
+                                let (i, j, k) = (bx * brick_size + x, by * brick_size + y, bz * brick_size + z);
+                                if i < nx && j < ny && k < nz {
+                                    let d = grid.voxel(i, j, k);
+                                    occupied |= d > 0.0;
+                                    values[(z * brick_size + y) * brick_size + x] = d;
+                                }
+                            }
+                        }
+                    }
+                    bricks.push(if occupied { Some(values.into_boxed_slice()) } else { None });
+                }
+            }
+        }
+
+        Self {
+            nx,
+            ny,
+            nz,
+            brick_size,
+            bricks_x,
+            bricks_y,
+            bricks,
+            max: grid.max_density(),
+        }
+    }
+
+    /// Number of bricks that actually hold data.
+    #[cfg(test)]
+    pub fn allocated_bricks(&self) -> usize {
+        self.bricks.iter().filter(|b| b.is_some()).count()
+    }
+}
+
+impl DensityGrid for SparseGrid {
+    fn resolution(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.nz)
+    }
+
+    fn voxel(&self, i: usize, j: usize, k: usize) -> f32 {
+        let b = self.brick_size;
+        let brick = ((k / b) * self.bricks_y + j / b) * self.bricks_x + i / b;
+        match &self.bricks[brick] {
+            Some(values) => values[((k % b) * b + j % b) * b + i % b],
+            None => 0.0,
+        }
+    }
+
+    fn max_density(&self) -> f32 {
+        self.max
+    }
+}
+
+/// Heterogeneous participating medium defined by a density grid stretched over a box.
+/// Scattering distances are sampled with delta tracking against the grid's maximum density,
+/// which is unbiased no matter how the density varies inside the box.
+pub struct HeterogeneousMedium<G: DensityGrid> {
+    grid: G,
+    bounds: Aabb,
+    /// Multiplier applied to the grid values to get the extinction coefficient.
+    pub density_scale: f32,
+    /// Phase function used when a ray scatters inside the volume.
+    pub phase_function: Material,
+}
+
+impl<G: DensityGrid> HeterogeneousMedium<G> {
+    pub fn new(grid: G, bounds: Aabb, density_scale: f32, phase_function: Material) -> Self {
+        Self {
+            grid,
+            bounds,
+            density_scale,
+            phase_function,
+        }
+    }
+
+    /// Extinction coefficient at a world space point.
+    pub fn density_at(&self, p: &Point3) -> f32 {
+        let size = self.bounds.size();
+        let local = *p - self.bounds.min;
+        let uvw = Point3::build(local.x / size.x, local.y / size.y, local.z / size.z);
+        self.grid.density(&uvw) * self.density_scale
+    }
+
+    fn majorant(&self) -> f32 {
+        self.grid.max_density() * self.density_scale
+    }
+}
+
+impl<G: DensityGrid> Hittable for HeterogeneousMedium<G> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord, rng: &mut Rng) -> bool {
+        stats::count(Counter::VolumeTests);
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return false;
+        }
+        let segment = match self.bounds.hit(r, ray_t) {
+            Some(segment) => segment,
+            None => return false,
+        };
+
+        // Delta tracking: take exponential steps with the majorant and accept a real
+        // collision with probability density / majorant, otherwise it was a null collision.
+        let ray_length = r.direction().length();
+        let mut t = segment.min;
+        loop {
+            t -= (1.0 - rng.next_f32()).ln() / (majorant * ray_length);
+            if t >= segment.max {
+                return false;
+            }
+            let point = r.at(t);
+            if rng.next_f32() * majorant < self.density_at(&point) {
+                rec.t = t;
+                rec.point = point;
+                rec.normal = Vec3::build(1.0, 0.0, 0.0);
+                rec.front_face = true;
+                rec.material = self.phase_function;
+                return true;
+            }
+        }
+    }
+
+    /// Unbiased estimate of the transmittance along the ray over `ray_t` using ratio tracking.
+    /// Unlike delta tracking it never returns a hard 0 or 1, which makes shadow rays through
+    /// thin media much less noisy.
+    fn transmittance(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> f32 {
+        stats::count(Counter::VolumeTests);
+        let majorant = self.majorant();
+        let segment = match self.bounds.hit(r, ray_t) {
+            Some(segment) => segment,
+            None => return 1.0,
+        };
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let ray_length = r.direction().length();
+        let mut transmittance = 1.0;
+        let mut t = segment.min;
+        loop {
+            t -= (1.0 - rng.next_f32()).ln() / (majorant * ray_length);
+            if t >= segment.max {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(&r.at(t)) / majorant;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant_grid(value: f32) -> DenseGrid {
+        DenseGrid::new(2, 2, 2, vec![value; 8])
+    }
+
+    #[test]
+    fn test_trilinear_lookup() {
+        // Density ramps from 0 to 1 along x
+        let grid = DenseGrid::from_fn(2, 1, 1, |p| if p.x < 0.5 { 0.0 } else { 1.0 });
+        assert_eq!(grid.density(&Point3::build(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(&Point3::build(0.75, 0.5, 0.5)), 1.0);
+        assert!((grid.density(&Point3::build(0.5, 0.5, 0.5)) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        let grid = DenseGrid::from_fn(3, 2, 4, |p| p.x + p.y * p.z);
+        let loaded = DenseGrid::from_raw_bytes(&grid.to_raw_bytes()).unwrap();
+        assert_eq!(loaded.resolution(), (3, 2, 4));
+        assert_eq!(loaded.data, grid.data);
+    }
+
+    #[test]
+    fn test_raw_rejects_truncated_data() {
+        let mut bytes = constant_grid(1.0).to_raw_bytes();
+        bytes.pop();
+        assert!(DenseGrid::from_raw_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_raw_rejects_oversized_header() {
+        // 2^21 voxels on each side make a byte count that overflows 64 bits
+        let mut bytes = Vec::new();
+        for n in [1u32 << 21, 1 << 21, 1 << 21] {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0; 16]);
+        assert!(DenseGrid::from_raw_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_raw_rejects_invalid_densities() {
+        for value in [f32::NAN, f32::INFINITY, -0.5] {
+            let mut bytes = constant_grid(1.0).to_raw_bytes();
+            bytes[12..16].copy_from_slice(&value.to_le_bytes());
+            let error = DenseGrid::from_raw_bytes(&bytes).err().expect("invalid density accepted");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "finite and non-negative")]
+    fn test_new_rejects_negative_densities() {
+        DenseGrid::new(1, 1, 2, vec![1.0, -1.0]);
+    }
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        let dense = DenseGrid::from_fn(10, 10, 10, |p| if p.x > 0.7 { p.y } else { 0.0 });
+        let sparse = SparseGrid::from_dense(&dense, 4);
+        assert!(sparse.allocated_bricks() < 27);
+        for (i, j, k) in [(0, 0, 0), (9, 3, 7), (8, 9, 9), (5, 5, 5)] {
+            assert_eq!(sparse.voxel(i, j, k), dense.voxel(i, j, k));
+        }
+    }
+
+    #[test]
+    fn test_ratio_tracking_matches_beer_lambert() {
+        let bounds = Aabb::new(&Point3::new(), &Point3::build(1.0, 1.0, 1.0));
+        let medium = HeterogeneousMedium::new(constant_grid(1.0), bounds, 2.0, Material::default());
+        let r = Ray::new(&Point3::build(-1.0, 0.5, 0.5), &Vec3::build(1.0, 0.0, 0.0));
+
+        let mut rng = Rng::new(5);
+        let n = 20000;
+        let sum: f32 = (0..n)
+            .map(|_| medium.transmittance(&r, &Interval::with_bounds(0.0, f32::INFINITY), &mut rng))
+            .sum();
+        // A constant grid has no null collisions so every estimate is exactly 0 or 1
+        let expected = (-2.0f32).exp();
+        assert!((sum / n as f32 - expected).abs() < 0.02);
+    }
+}